@@ -221,7 +221,7 @@ export const useBreeding = (parentsCreator: string) => {
         programId
       )
 
-      const { init } = createBreeding(
+      const { init, revealWhenReady } = createBreeding(
         connection,
         anchorProgram,
         breedingMachine,
@@ -230,12 +230,25 @@ export const useBreeding = (parentsCreator: string) => {
 
       setFeedbackStatus("Initializing...")
 
-      const { tx } = await init(mintParentA, mintParentB)
+      const { tx } = await init([mintParentA, mintParentB])
 
       setFeedbackStatus("Confirming transaction...")
 
       await connection.confirmTransaction(tx, "confirmed")
 
+      /**
+       * Breedings rolling their outcome fail once their reveal slot left the
+       * SlotHashes sysvar, about 3 minutes later, unless the outcome was
+       * revealed.
+       */
+      setFeedbackStatus("Revealing outcome...")
+
+      const revealed = await revealWhenReady([mintParentA, mintParentB])
+
+      if (revealed) {
+        await connection.confirmTransaction(revealed.tx, "confirmed")
+      }
+
       setFeedbackStatus("Refetching data...")
 
       await fetchData()
@@ -283,7 +296,7 @@ export const useBreeding = (parentsCreator: string) => {
 
       setFeedbackStatus("Terminating...")
 
-      const { tx } = await terminate(mintParentA, mintParentB)
+      const { tx } = await terminate([mintParentA, mintParentB])
 
       setFeedbackStatus("Confirming transaction...")
 
@@ -339,14 +352,14 @@ export const useBreeding = (parentsCreator: string) => {
       const {
         instruction: initInstruction,
         additionalInstructions: initAdditional,
-      } = await getInitInstruction(mintParentA, mintParentB)
+      } = await getInitInstruction([mintParentA, mintParentB])
 
       const ixInit = await initInstruction.instruction()
 
       const {
         instruction: terminateInstruction,
         additionalInstructions: terminateAdditional,
-      } = await getTerminateInstruction(mintParentA, mintParentB)
+      } = await getTerminateInstruction([mintParentA, mintParentB])
 
       const ixTerminate = await terminateInstruction.instruction()
 
@@ -1,5 +1,5 @@
 import { programs } from "@metaplex/js"
-import { Idl, Program, utils, web3 } from "@project-serum/anchor"
+import { BN, Idl, Program, utils, web3 } from "@project-serum/anchor"
 import {
   ASSOCIATED_TOKEN_PROGRAM_ID,
   Token,
@@ -12,10 +12,14 @@ const incineratorAddress = new web3.PublicKey(
   "1nc1nerator11111111111111111111111111111111"
 )
 
+/**
+ * The machine address is derived from the wallet that created it, which stays
+ * the same after the authority is transferred.
+ */
 export const findBreedingMachineAddress = (
   parentsCandyMachine: web3.PublicKey,
   rewardCandyMachine: web3.PublicKey,
-  authority: web3.PublicKey,
+  creator: web3.PublicKey,
   breedingProgram: web3.PublicKey
 ) =>
   utils.publicKey.findProgramAddressSync(
@@ -23,26 +27,38 @@ export const findBreedingMachineAddress = (
       Buffer.from("breed_machine"),
       parentsCandyMachine.toBuffer(),
       rewardCandyMachine.toBuffer(),
-      authority.toBuffer(),
+      creator.toBuffer(),
     ],
     breedingProgram
   )[0]
 
+/**
+ * The program expects parents sorted by mint address, so each set of parents
+ * has a single breed data account.
+ */
+export const sortParents = (mints: web3.PublicKey[]): web3.PublicKey[] =>
+  [...mints].sort((a, b) => Buffer.compare(a.toBuffer(), b.toBuffer()))
+
+/**
+ * Parents are locked while breeding, so the breed data is derived from the
+ * first sorted parent only.
+ */
 export const findBreedDataAddress = (
   breedMachineAddress: web3.PublicKey,
-  mintAddressA: web3.PublicKey,
-  mintAddressB: web3.PublicKey,
+  mints: web3.PublicKey[],
   breedingProgram: web3.PublicKey
-) =>
-  utils.publicKey.findProgramAddressSync(
+) => {
+  const [first] = sortParents(mints)
+
+  return utils.publicKey.findProgramAddressSync(
     [
       Buffer.from("breed_account"),
       breedMachineAddress.toBuffer(),
-      mintAddressA.toBuffer(),
-      mintAddressB.toBuffer(),
+      first.toBuffer(),
     ],
     breedingProgram
   )[0]
+}
 
 export const findWhitelistTokenAddress = (
   breedingMachine: web3.PublicKey,
@@ -53,6 +69,129 @@ export const findWhitelistTokenAddress = (
     breedingProgram
   )[0]
 
+/**
+ * Whitelist token of an extra reward tier, numbered from 1.
+ */
+export const findRewardTierTokenAddress = (
+  breedingMachine: web3.PublicKey,
+  tier: number,
+  breedingProgram: web3.PublicKey
+) =>
+  utils.publicKey.findProgramAddressSync(
+    [
+      Buffer.from("whitelist_token"),
+      breedingMachine.toBuffer(),
+      Buffer.from([tier]),
+    ],
+    breedingProgram
+  )[0]
+
+/**
+ * Mint and vault of each extra reward tier, then the user ATA on finalize,
+ * which the program creates when missing.
+ */
+export const getRewardTierAccounts = async (
+  breedingMachine: web3.PublicKey,
+  extraRewardTiers: number,
+  breedingProgram: web3.PublicKey,
+  user?: web3.PublicKey
+): Promise<web3.AccountMeta[]> => {
+  const accounts: web3.AccountMeta[] = []
+
+  for (let tier = 1; tier <= extraRewardTiers; tier++) {
+    const mint = findRewardTierTokenAddress(
+      breedingMachine,
+      tier,
+      breedingProgram
+    )
+    const vault = await utils.token.associatedAddress({
+      mint,
+      owner: breedingMachine,
+    })
+    accounts.push(
+      { pubkey: mint, isWritable: true, isSigner: false },
+      { pubkey: vault, isWritable: true, isSigner: false }
+    )
+
+    if (user) {
+      const userAta = await utils.token.associatedAddress({
+        mint,
+        owner: user,
+      })
+      accounts.push({ pubkey: userAta, isWritable: true, isSigner: false })
+    }
+  }
+
+  return accounts
+}
+
+export const findParentRecordAddress = (
+  mint: web3.PublicKey,
+  breedingProgram: web3.PublicKey
+) =>
+  utils.publicKey.findProgramAddressSync(
+    [Buffer.from("parent_record"), mint.toBuffer()],
+    breedingProgram
+  )[0]
+
+/**
+ * Children minted on finalize are derived from the machine `born` counter when
+ * they are minted.
+ */
+export const findChildMintAddress = (
+  breedingMachine: web3.PublicKey,
+  born: BN,
+  breedingProgram: web3.PublicKey
+) =>
+  utils.publicKey.findProgramAddressSync(
+    [
+      Buffer.from("child_mint"),
+      breedingMachine.toBuffer(),
+      born.toArrayLike(Buffer, "le", 8),
+    ],
+    breedingProgram
+  )[0]
+
+/**
+ * Lineage of a child NFT minted on finalize.
+ */
+export const findLineageAddress = (
+  childMint: web3.PublicKey,
+  breedingProgram: web3.PublicKey
+) =>
+  utils.publicKey.findProgramAddressSync(
+    [Buffer.from("lineage"), childMint.toBuffer()],
+    breedingProgram
+  )[0]
+
+/**
+ * Lineage of a breeding rewarded with a whitelist token, derived from the
+ * machine `born` counter when it was finalized.
+ */
+export const findBreedLineageAddress = (
+  breedingMachine: web3.PublicKey,
+  breedId: BN,
+  breedingProgram: web3.PublicKey
+) =>
+  utils.publicKey.findProgramAddressSync(
+    [
+      Buffer.from("lineage"),
+      breedingMachine.toBuffer(),
+      breedId.toArrayLike(Buffer, "le", 8),
+    ],
+    breedingProgram
+  )[0]
+
+/**
+ * How many remaining accounts each instruction takes per parent. Accounts go
+ * in this order: mint, user ATA, vault ATA, parent record and metadata.
+ */
+export const ACCOUNTS_PER_PARENT = {
+  init: 5,
+  finalize: 4,
+  cancel: 3,
+}
+
 /**
  * Handles init and terminate breeding
  *
@@ -73,35 +212,94 @@ export const createBreeding = (
     breedingProgram.programId
   )
 
+  /**
+   * Returns the lock mode of a breeding. Breedings initialized in the same
+   * transaction don't exist yet, and will take the current machine config.
+   */
+  const getLockMode = async (breedData: web3.PublicKey) => {
+    const breedDataAccount =
+      await breedingProgram.account.breedData.fetchNullable(breedData)
+
+    if (breedDataAccount) return breedDataAccount.lockMode
+
+    const { config } = await breedingProgram.account.breedMachine.fetch(
+      breedingMachineAddress
+    )
+
+    return config.lockMode
+  }
+
+  /**
+   * Returns the remaining accounts of the parents, sorted by mint.
+   *
+   * The lock account is the breed data ATA in vault mode, and the master
+   * edition in freeze mode, where the parents stay frozen in the user ATAs.
+   */
+  const getParentAccounts = async (
+    mints: web3.PublicKey[],
+    breedData: web3.PublicKey,
+    lockMode: { vault?: {}; freeze?: {} },
+    accountsPerParent: number
+  ) => {
+    const userAtas: web3.PublicKey[] = []
+    const vaultAtas: web3.PublicKey[] = []
+    const remainingAccounts: web3.AccountMeta[] = []
+
+    for (const mint of mints) {
+      const userAta = await utils.token.associatedAddress({
+        mint,
+        owner: userWallet.publicKey,
+      })
+
+      const vaultAta = await utils.token.associatedAddress({
+        mint,
+        owner: breedData,
+      })
+
+      const lockAccount: web3.AccountMeta = lockMode.freeze
+        ? {
+            pubkey: await programs.metadata.MasterEdition.getPDA(mint),
+            isSigner: false,
+            isWritable: false,
+          }
+        : { pubkey: vaultAta, isSigner: false, isWritable: true }
+
+      const metadata = await programs.metadata.Metadata.getPDA(mint)
+
+      const parentAccounts: web3.AccountMeta[] = [
+        { pubkey: mint, isSigner: false, isWritable: true },
+        { pubkey: userAta, isSigner: false, isWritable: true },
+        lockAccount,
+        {
+          pubkey: findParentRecordAddress(mint, breedingProgram.programId),
+          isSigner: false,
+          isWritable: true,
+        },
+        { pubkey: metadata, isSigner: false, isWritable: false },
+      ]
+
+      userAtas.push(userAta)
+      vaultAtas.push(vaultAta)
+      remainingAccounts.push(...parentAccounts.slice(0, accountsPerParent))
+    }
+
+    return { userAtas, vaultAtas, remainingAccounts }
+  }
+
   /**
    * Calls init method through RPC
    */
   const init = async (
-    mintParentA: web3.PublicKey,
-    mintParentB: web3.PublicKey,
+    mints: web3.PublicKey[],
     signers: web3.Keypair[] = []
   ) => {
     try {
-      if (!mintParentA || !mintParentB)
-        throw new Error("Mint addresses are missing.")
-
-      const {
-        instruction,
-        userAtaParentA,
-        userAtaParentB,
-        vaultAtaParentA,
-        vaultAtaParentB,
-      } = await getInitInstruction(mintParentA, mintParentB, signers)
+      const { instruction, breedData, userAtas, vaultAtas } =
+        await getInitInstruction(mints, signers)
 
       const tx = await instruction.rpc()
 
-      return {
-        tx,
-        userAtaParentA,
-        userAtaParentB,
-        vaultAtaParentA,
-        vaultAtaParentB,
-      }
+      return { tx, breedData, userAtas, vaultAtas }
     } catch (e) {
       console.log(e)
 
@@ -113,45 +311,42 @@ export const createBreeding = (
    * Returns instruction for init method
    */
   const getInitInstruction = async (
-    mintParentA: web3.PublicKey,
-    mintParentB: web3.PublicKey,
+    mints: web3.PublicKey[],
     signers: web3.Keypair[] = []
   ) => {
     try {
-      if (!mintParentA || !mintParentB)
-        throw new Error("Mint addresses are missing.")
+      if (!mints.length) throw new Error("Mint addresses are missing.")
+
+      const parents = sortParents(mints)
 
       const breedingMachineAccount =
         await breedingProgram.account.breedMachine.fetch(breedingMachineAddress)
 
-      const feeToken = breedingMachineAccount.config.initializationFeeToken
+      const { config } = breedingMachineAccount
+
+      /**
+       * Fee accounts the machine config doesn't use are filled with the user
+       * wallet.
+       */
+      const chargesTokenFee = !config.initializationFeePrice.isZero()
+
+      const feeToken = chargesTokenFee
+        ? config.initializationFeeToken
+        : userWallet.publicKey
 
       const breedData = findBreedDataAddress(
         breedingMachineAddress,
-        mintParentA,
-        mintParentB,
+        parents,
         breedingProgram.programId
       )
 
-      const userAtaParentA = await utils.token.associatedAddress({
-        mint: mintParentA,
-        owner: userWallet.publicKey,
-      })
-
-      const userAtaParentB = await utils.token.associatedAddress({
-        mint: mintParentB,
-        owner: userWallet.publicKey,
-      })
-
-      const vaultAtaParentA = await utils.token.associatedAddress({
-        mint: mintParentA,
-        owner: breedData,
-      })
-
-      const vaultAtaParentB = await utils.token.associatedAddress({
-        mint: mintParentB,
-        owner: breedData,
-      })
+      const { userAtas, vaultAtas, remainingAccounts } =
+        await getParentAccounts(
+          parents,
+          breedData,
+          config.lockMode,
+          ACCOUNTS_PER_PARENT.init
+        )
 
       /**
        * Additional instructions:
@@ -160,16 +355,18 @@ export const createBreeding = (
        */
       const additionalInstructions = []
 
-      const feePayerAtaAddress = await utils.token.associatedAddress({
-        mint: feeToken,
-        owner: userWallet.publicKey,
-      })
+      const feePayerAtaAddress = chargesTokenFee
+        ? await utils.token.associatedAddress({
+            mint: feeToken,
+            owner: userWallet.publicKey,
+          })
+        : userWallet.publicKey
 
       const feePayerAtaAccountInfo = await connection.getAccountInfo(
         feePayerAtaAddress
       )
 
-      if (!feePayerAtaAccountInfo) {
+      if (chargesTokenFee && !feePayerAtaAccountInfo) {
         const createAtaInstruction =
           Token.createAssociatedTokenAccountInstruction(
             ASSOCIATED_TOKEN_PROGRAM_ID,
@@ -183,45 +380,52 @@ export const createBreeding = (
         additionalInstructions.push(createAtaInstruction)
       }
 
-      const metadataParentA = await programs.metadata.Metadata.getPDA(
-        mintParentA
-      )
-      const metadataParentB = await programs.metadata.Metadata.getPDA(
-        mintParentB
+      /** The token fee is escrowed in a vault owned by the breed data. */
+      const feeVault = chargesTokenFee
+        ? await utils.token.associatedAddress({
+            mint: feeToken,
+            owner: breedData,
+          })
+        : userWallet.publicKey
+
+      /** The whitelist vaults are read to reserve the breeding reward. */
+      const whitelistVault = await utils.token.associatedAddress({
+        mint: whitelistToken,
+        owner: breedingMachineAddress,
+      })
+      const rewardTierAccounts = await getRewardTierAccounts(
+        breedingMachineAddress,
+        breedingMachineAccount.extraRewardTiers.length,
+        breedingProgram.programId
       )
 
       // setFeedbackStatus("[Breed] Sending transaction...")
       const instruction = breedingProgram.methods
-        .initializeBreeding()
+        .initializeBreeding(parents)
         .accounts({
           breedingMachine: breedingMachineAddress,
           breedData,
 
-          mintParentA,
-          metadataParentA,
-          userAtaParentA,
-          vaultAtaParentA,
-
-          mintParentB,
-          metadataParentB,
-          userAtaParentB,
-          vaultAtaParentB,
-
           feeToken,
           feePayerAta: feePayerAtaAddress,
+          feeVault,
+
+          whitelistToken,
+          whitelistVault,
 
           userWallet: userWallet.publicKey,
+          tokenMetadataProgram: programs.metadata.MetadataProgram.PUBKEY,
         })
+        .remainingAccounts([...remainingAccounts, ...rewardTierAccounts])
         .preInstructions(additionalInstructions)
         .signers(signers)
 
       return {
         instruction,
         additionalInstructions,
-        userAtaParentA,
-        userAtaParentB,
-        vaultAtaParentA,
-        vaultAtaParentB,
+        breedData,
+        userAtas,
+        vaultAtas,
       }
     } catch (e) {
       console.log(e)
@@ -230,81 +434,182 @@ export const createBreeding = (
     }
   }
 
+  /**
+   * Returns the accounts settling the fees escrowed by a breeding. Accounts
+   * the breeding doesn't use are filled with the user wallet.
+   */
+  const getFeeEscrowAccounts = async (breedData: web3.PublicKey) => {
+    const { config } = await breedingProgram.account.breedMachine.fetch(
+      breedingMachineAddress
+    )
+
+    /**
+     * Breedings initialized in the same transaction don't exist yet, and will
+     * take the current machine config.
+     */
+    const breedDataAccount =
+      (await breedingProgram.account.breedData.fetchNullable(breedData)) || {
+        feeToken: config.initializationFeeToken,
+        feePaid: config.initializationFeePrice,
+        solFeePaid: config.solFeeLamports,
+        feeMode: config.feeMode,
+        feeTreasury: config.feeTreasury,
+        solFeeTreasury: config.solFeeTreasury,
+      }
+
+    const paidTokenFee = !breedDataAccount.feePaid.isZero()
+
+    const feeToken = paidTokenFee
+      ? breedDataAccount.feeToken
+      : userWallet.publicKey
+
+    const feeVault = paidTokenFee
+      ? await utils.token.associatedAddress({
+          mint: feeToken,
+          owner: breedData,
+        })
+      : userWallet.publicKey
+
+    const feePayerAta = paidTokenFee
+      ? await utils.token.associatedAddress({
+          mint: feeToken,
+          owner: userWallet.publicKey,
+        })
+      : userWallet.publicKey
+
+    const feeTreasury =
+      paidTokenFee && !breedDataAccount.feeMode.burn
+        ? breedDataAccount.feeTreasury
+        : userWallet.publicKey
+
+    const solFeeTreasury = breedDataAccount.solFeePaid.isZero()
+      ? userWallet.publicKey
+      : breedDataAccount.solFeeTreasury
+
+    return { feeToken, feeVault, feePayerAta, feeTreasury, solFeeTreasury }
+  }
+
+  /**
+   * Returns the accounts minting the child NFT on finalize and its lineage.
+   * Filled with the user wallet when the machine sends whitelist tokens
+   * instead.
+   */
+  const getChildNftAccounts = async () => {
+    const { childNft, born } = await breedingProgram.account.breedMachine.fetch(
+      breedingMachineAddress
+    )
+
+    if (!childNft) {
+      return {
+        childMint: userWallet.publicKey,
+        childMetadata: userWallet.publicKey,
+        childMasterEdition: userWallet.publicKey,
+        childAta: userWallet.publicKey,
+        collectionMint: userWallet.publicKey,
+        collectionMetadata: userWallet.publicKey,
+        collectionMasterEdition: userWallet.publicKey,
+        childParentRecord: userWallet.publicKey,
+        lineage: findBreedLineageAddress(
+          breedingMachineAddress,
+          born,
+          breedingProgram.programId
+        ),
+      }
+    }
+
+    const childMint = findChildMintAddress(
+      breedingMachineAddress,
+      born,
+      breedingProgram.programId
+    )
+
+    return {
+      childMint,
+      childMetadata: await programs.metadata.Metadata.getPDA(childMint),
+      childMasterEdition: await programs.metadata.MasterEdition.getPDA(
+        childMint
+      ),
+      childAta: await utils.token.associatedAddress({
+        mint: childMint,
+        owner: userWallet.publicKey,
+      }),
+      collectionMint: childNft.collection,
+      collectionMetadata: await programs.metadata.Metadata.getPDA(
+        childNft.collection
+      ),
+      collectionMasterEdition: await programs.metadata.MasterEdition.getPDA(
+        childNft.collection
+      ),
+      childParentRecord: findParentRecordAddress(
+        childMint,
+        breedingProgram.programId
+      ),
+      lineage: findLineageAddress(childMint, breedingProgram.programId),
+    }
+  }
+
   /**
    * Calls terminate method through RPC
    */
   const terminate = async (
-    mintParentA: web3.PublicKey,
-    mintParentB: web3.PublicKey,
+    mints: web3.PublicKey[],
     signers: web3.Keypair[] = []
   ) => {
-    if (!mintParentA || !mintParentB)
-      throw new Error("Mint addresses are missing.")
-
     const {
       instruction,
       userWhitelistAta,
+      childAccounts,
       breedData,
-      userAtaParentB,
-      userAtaParentA,
-    } = await getTerminateInstruction(mintParentA, mintParentB, signers)
+      userAtas,
+    } = await getTerminateInstruction(mints, signers)
 
     const tx = await instruction.rpc()
-    return { tx, userWhitelistAta, breedData, userAtaParentB, userAtaParentA }
+    return { tx, userWhitelistAta, childAccounts, breedData, userAtas }
   }
 
   /**
    * Returns instruction for terminate method
    */
   const getTerminateInstruction = async (
-    mintParentA: web3.PublicKey,
-    mintParentB: web3.PublicKey,
+    mints: web3.PublicKey[],
     signers: web3.Keypair[] = []
   ) => {
-    if (!mintParentA || !mintParentB)
-      throw new Error("Mint addresses are missing.")
+    if (!mints.length) throw new Error("Mint addresses are missing.")
+
+    const parents = sortParents(mints)
 
     const breedData = findBreedDataAddress(
       breedingMachineAddress,
-      mintParentA,
-      mintParentB,
+      parents,
       breedingProgram.programId
     )
 
-    const userAtaParentA = await utils.token.associatedAddress({
-      mint: mintParentA,
-      owner: userWallet.publicKey,
-    })
-
-    const userAtaParentB = await utils.token.associatedAddress({
-      mint: mintParentB,
-      owner: userWallet.publicKey,
-    })
-
-    const vaultAtaParentA = await utils.token.associatedAddress({
-      mint: mintParentA,
-      owner: breedData,
-    })
-
-    const vaultAtaParentB = await utils.token.associatedAddress({
-      mint: mintParentB,
-      owner: breedData,
-    })
+    const { userAtas, remainingAccounts } = await getParentAccounts(
+      parents,
+      breedData,
+      await getLockMode(breedData),
+      ACCOUNTS_PER_PARENT.finalize
+    )
 
     const whitelistVault = await utils.token.associatedAddress({
       mint: whitelistToken,
       owner: breedingMachineAddress,
     })
 
-    const userWhitelistAta = await utils.token.associatedAddress({
-      mint: whitelistToken,
-      owner: userWallet.publicKey,
-    })
+    const childAccounts = await getChildNftAccounts()
+    const mintsChild = !childAccounts.childMint.equals(userWallet.publicKey)
+
+    const userWhitelistAta = mintsChild
+      ? userWallet.publicKey
+      : await utils.token.associatedAddress({
+          mint: whitelistToken,
+          owner: userWallet.publicKey,
+        })
 
     /**
      * Additional instructions:
      *
-     * Create userWhitelistAta
+     * Create userWhitelistAta when the machine sends whitelist tokens
      */
     const additionalInstructions = []
 
@@ -312,7 +617,7 @@ export const createBreeding = (
       userWhitelistAta
     )
 
-    if (!userWhitelistAtaAccountInfo) {
+    if (!mintsChild && !userWhitelistAtaAccountInfo) {
       const createAtaInstruction =
         Token.createAssociatedTokenAccountInstruction(
           ASSOCIATED_TOKEN_PROGRAM_ID,
@@ -326,27 +631,40 @@ export const createBreeding = (
       additionalInstructions.push(createAtaInstruction)
     }
 
+    const { feeToken, feeVault, feeTreasury, solFeeTreasury } =
+      await getFeeEscrowAccounts(breedData)
+
+    const { extraRewardTiers } =
+      await breedingProgram.account.breedMachine.fetch(breedingMachineAddress)
+    const rewardTierAccounts = await getRewardTierAccounts(
+      breedingMachineAddress,
+      extraRewardTiers.length,
+      breedingProgram.programId,
+      userWallet.publicKey
+    )
+
     const instruction = breedingProgram.methods
       .finalizeBreeding()
       .accounts({
         breedingMachine: breedingMachineAddress,
         breedData,
 
-        mintParentA,
-        mintParentB,
-
-        userAtaParentA,
-        userAtaParentB,
-
-        vaultAtaParentA,
-        vaultAtaParentB,
-
         whitelistToken,
         whitelistVault,
         userWhitelistAta,
 
+        ...childAccounts,
+        tokenMetadataProgram: programs.metadata.MetadataProgram.PUBKEY,
+
+        feeToken,
+        feeVault,
+        feeTreasury,
+        solFeeTreasury,
+
         userWallet: userWallet.publicKey,
+        slotHashes: web3.SYSVAR_SLOT_HASHES_PUBKEY,
       })
+      .remainingAccounts([...remainingAccounts, ...rewardTierAccounts])
       .preInstructions(additionalInstructions)
       .signers(signers)
 
@@ -354,46 +672,86 @@ export const createBreeding = (
       instruction,
       additionalInstructions,
       userWhitelistAta,
+      childAccounts,
       breedData,
-      userAtaParentB,
-      userAtaParentA,
+      userAtas,
     }
   }
 
+  /**
+   * Stores the outcome seed of a breeding, so it can be finalized after its
+   * reveal slot left the SlotHashes sysvar, about 3 minutes after it started.
+   * Anyone can send it.
+   */
+  const reveal = async (mints: web3.PublicKey[]) => {
+    if (!mints.length) throw new Error("Mint addresses are missing.")
+
+    const breedData = findBreedDataAddress(
+      breedingMachineAddress,
+      sortParents(mints),
+      breedingProgram.programId
+    )
+
+    const tx = await breedingProgram.methods
+      .revealBreeding()
+      .accounts({
+        breedData,
+        slotHashes: web3.SYSVAR_SLOT_HASHES_PUBKEY,
+      })
+      .rpc()
+
+    return { tx, breedData }
+  }
+
+  /**
+   * Waits for the reveal slot of a breeding, the one after it started, then
+   * reveals its outcome. Returns null for breedings that don't roll their
+   * outcome, which never need it.
+   */
+  const revealWhenReady = async (mints: web3.PublicKey[]) => {
+    if (!mints.length) throw new Error("Mint addresses are missing.")
+
+    const breedData = findBreedDataAddress(
+      breedingMachineAddress,
+      sortParents(mints),
+      breedingProgram.programId
+    )
+
+    const { rolls, commitSlot } =
+      await breedingProgram.account.breedData.fetch(breedData)
+    if (!rolls) return null
+
+    // The reveal slot hash is known once the next slot starts.
+    while ((await connection.getSlot()) <= commitSlot.toNumber() + 1) {
+      await new Promise((resolve) => setTimeout(resolve, 400))
+    }
+
+    return reveal(mints)
+  }
+
   const cancel = async (
-    mintParentA: web3.PublicKey,
-    mintParentB: web3.PublicKey,
+    mints: web3.PublicKey[],
     signers: web3.Keypair[] = []
   ) => {
-    if (!mintParentA || !mintParentB)
-      throw new Error("Mint addresses are missing.")
+    if (!mints.length) throw new Error("Mint addresses are missing.")
+
+    const parents = sortParents(mints)
 
     const breedData = findBreedDataAddress(
       breedingMachineAddress,
-      mintParentA,
-      mintParentB,
+      parents,
       breedingProgram.programId
     )
 
-    const userAtaParentA = await utils.token.associatedAddress({
-      mint: mintParentA,
-      owner: userWallet.publicKey,
-    })
-
-    const userAtaParentB = await utils.token.associatedAddress({
-      mint: mintParentB,
-      owner: userWallet.publicKey,
-    })
-
-    const vaultAtaParentA = await utils.token.associatedAddress({
-      mint: mintParentA,
-      owner: breedData,
-    })
+    const { userAtas, remainingAccounts } = await getParentAccounts(
+      parents,
+      breedData,
+      await getLockMode(breedData),
+      ACCOUNTS_PER_PARENT.cancel
+    )
 
-    const vaultAtaParentB = await utils.token.associatedAddress({
-      mint: mintParentB,
-      owner: breedData,
-    })
+    const { feeToken, feeVault, feePayerAta, feeTreasury, solFeeTreasury } =
+      await getFeeEscrowAccounts(breedData)
 
     const tx = await breedingProgram.methods
       .cancelBreeding()
@@ -401,25 +759,26 @@ export const createBreeding = (
         breedingMachine: breedingMachineAddress,
         breedData,
 
-        mintParentA,
-        mintParentB,
-
-        userAtaParentA,
-        userAtaParentB,
-
-        vaultAtaParentA,
-        vaultAtaParentB,
+        feeToken,
+        feeVault,
+        feePayerAta,
+        feeTreasury,
+        solFeeTreasury,
 
         userWallet: userWallet.publicKey,
+        tokenMetadataProgram: programs.metadata.MetadataProgram.PUBKEY,
       })
+      .remainingAccounts(remainingAccounts)
       .signers(signers)
       .rpc()
 
-    return { tx, breedData, userAtaParentB, userAtaParentA }
+    return { tx, breedData, userAtas }
   }
 
   return {
     init,
+    reveal,
+    revealWhenReady,
     terminate,
     cancel,
     getTerminateInstruction,
@@ -0,0 +1,284 @@
+//! Machine config files, in TOML or JSON depending on their extension.
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::{Context, Result};
+use breed_client::{
+    breed_program::{CancelPolicy, FeeMode, LockMode, ParentValidation, MAX_BPS},
+    BreedConfig, UpdateMachineConfigArgs,
+};
+use serde::{de::DeserializeOwned, Deserialize};
+use std::path::Path;
+
+/// Config of a new machine. Fee and parent checks are off unless set.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MachineConfig {
+    pub breeding_time: u64,
+    #[serde(default)]
+    pub burn_parents: bool,
+    #[serde(with = "from_str")]
+    pub parents_candy_machine: Pubkey,
+    #[serde(default, with = "option_from_str")]
+    pub parents_collection: Option<Pubkey>,
+    #[serde(default)]
+    pub parent_validation: ParentValidationConfig,
+    #[serde(default = "default_parents_count")]
+    pub parents_count: u8,
+    #[serde(with = "from_str")]
+    pub reward_candy_machine: Pubkey,
+    pub reward_supply: u64,
+    #[serde(default, with = "option_from_str")]
+    pub initialization_fee_token: Option<Pubkey>,
+    #[serde(default)]
+    pub initialization_fee_price: u64,
+    #[serde(default)]
+    pub fee_mode: FeeModeConfig,
+    #[serde(default, with = "option_from_str")]
+    pub fee_treasury: Option<Pubkey>,
+    #[serde(default)]
+    pub sol_fee_lamports: u64,
+    #[serde(default, with = "option_from_str")]
+    pub sol_fee_treasury: Option<Pubkey>,
+    #[serde(default)]
+    pub cancel_policy: CancelPolicyConfig,
+    #[serde(default)]
+    pub breeding_cooldown: u64,
+    #[serde(default)]
+    pub max_breeds_per_parent: u64,
+    #[serde(default)]
+    pub lock_mode: LockModeConfig,
+    #[serde(default = "default_success_bps")]
+    pub success_bps: u16,
+    // Keeps the whitelist mint authority to replenish the rewards up to this
+    // supply when set.
+    #[serde(default)]
+    pub reward_cap: Option<u64>,
+}
+
+fn default_parents_count() -> u8 {
+    2
+}
+
+fn default_success_bps() -> u16 {
+    MAX_BPS
+}
+
+impl From<MachineConfig> for BreedConfig {
+    fn from(config: MachineConfig) -> Self {
+        BreedConfig {
+            breeding_time: config.breeding_time,
+            burn_parents: config.burn_parents,
+            parents_candy_machine: config.parents_candy_machine,
+            parents_collection: config.parents_collection.unwrap_or_default(),
+            parent_validation: config.parent_validation.into(),
+            parents_count: config.parents_count,
+            reward_candy_machine: config.reward_candy_machine,
+            initialization_fee_token: config.initialization_fee_token.unwrap_or_default(),
+            initialization_fee_price: config.initialization_fee_price,
+            fee_mode: config.fee_mode.into(),
+            fee_treasury: config.fee_treasury.unwrap_or_default(),
+            sol_fee_lamports: config.sol_fee_lamports,
+            sol_fee_treasury: config.sol_fee_treasury.unwrap_or_default(),
+            cancel_policy: config.cancel_policy.into(),
+            reward_supply: config.reward_supply,
+            breeding_cooldown: config.breeding_cooldown,
+            max_breeds_per_parent: config.max_breeds_per_parent,
+            lock_mode: config.lock_mode.into(),
+            success_bps: config.success_bps,
+            mint_capped: config.reward_cap.is_some(),
+            reward_cap: config.reward_cap.unwrap_or_default(),
+        }
+    }
+}
+
+/// Changes to the config of a machine. Missing fields stay unchanged.
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ConfigUpdate {
+    pub breeding_time: Option<u64>,
+    pub burn_parents: Option<bool>,
+    #[serde(with = "option_from_str")]
+    pub parents_collection: Option<Pubkey>,
+    pub parent_validation: Option<ParentValidationConfig>,
+    pub parents_count: Option<u8>,
+    pub initialization_fee_price: Option<u64>,
+    pub breeding_cooldown: Option<u64>,
+    pub max_breeds_per_parent: Option<u64>,
+    pub fee_mode: Option<FeeModeConfig>,
+    #[serde(with = "option_from_str")]
+    pub fee_treasury: Option<Pubkey>,
+    pub sol_fee_lamports: Option<u64>,
+    #[serde(with = "option_from_str")]
+    pub sol_fee_treasury: Option<Pubkey>,
+    pub cancel_policy: Option<CancelPolicyConfig>,
+    pub lock_mode: Option<LockModeConfig>,
+    pub success_bps: Option<u16>,
+}
+
+impl From<ConfigUpdate> for UpdateMachineConfigArgs {
+    fn from(update: ConfigUpdate) -> Self {
+        UpdateMachineConfigArgs {
+            breeding_time: update.breeding_time,
+            burn_parents: update.burn_parents,
+            parents_collection: update.parents_collection,
+            parent_validation: update.parent_validation.map(Into::into),
+            parents_count: update.parents_count,
+            initialization_fee_price: update.initialization_fee_price,
+            breeding_cooldown: update.breeding_cooldown,
+            max_breeds_per_parent: update.max_breeds_per_parent,
+            fee_mode: update.fee_mode.map(Into::into),
+            fee_treasury: update.fee_treasury,
+            sol_fee_lamports: update.sol_fee_lamports,
+            sol_fee_treasury: update.sol_fee_treasury,
+            cancel_policy: update.cancel_policy.map(Into::into),
+            lock_mode: update.lock_mode.map(Into::into),
+            success_bps: update.success_bps,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ParentValidationConfig {
+    VerifiedCreator,
+    VerifiedCollection,
+    Both,
+}
+
+impl Default for ParentValidationConfig {
+    fn default() -> Self {
+        ParentValidationConfig::VerifiedCreator
+    }
+}
+
+impl From<ParentValidationConfig> for ParentValidation {
+    fn from(validation: ParentValidationConfig) -> Self {
+        match validation {
+            ParentValidationConfig::VerifiedCreator => ParentValidation::VerifiedCreator,
+            ParentValidationConfig::VerifiedCollection => ParentValidation::VerifiedCollection,
+            ParentValidationConfig::Both => ParentValidation::Both,
+        }
+    }
+}
+
+/// e.g. `fee_mode = { mode = "split", burn_bps = 5000 }`
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "mode", rename_all = "snake_case", deny_unknown_fields)]
+pub enum FeeModeConfig {
+    Burn,
+    Treasury,
+    Split { burn_bps: u16 },
+}
+
+impl Default for FeeModeConfig {
+    fn default() -> Self {
+        FeeModeConfig::Burn
+    }
+}
+
+impl From<FeeModeConfig> for FeeMode {
+    fn from(mode: FeeModeConfig) -> Self {
+        match mode {
+            FeeModeConfig::Burn => FeeMode::Burn,
+            FeeModeConfig::Treasury => FeeMode::Treasury,
+            FeeModeConfig::Split { burn_bps } => FeeMode::Split { burn_bps },
+        }
+    }
+}
+
+/// e.g. `cancel_policy = { policy = "partial_refund", refund_bps = 5000 }`
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(tag = "policy", rename_all = "snake_case", deny_unknown_fields)]
+pub enum CancelPolicyConfig {
+    FullRefund,
+    PartialRefund { refund_bps: u16 },
+    TimePenalty { max_penalty_bps: u16 },
+}
+
+impl Default for CancelPolicyConfig {
+    fn default() -> Self {
+        CancelPolicyConfig::FullRefund
+    }
+}
+
+impl From<CancelPolicyConfig> for CancelPolicy {
+    fn from(policy: CancelPolicyConfig) -> Self {
+        match policy {
+            CancelPolicyConfig::FullRefund => CancelPolicy::FullRefund,
+            CancelPolicyConfig::PartialRefund { refund_bps } => {
+                CancelPolicy::PartialRefund { refund_bps }
+            }
+            CancelPolicyConfig::TimePenalty { max_penalty_bps } => {
+                CancelPolicy::TimePenalty { max_penalty_bps }
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LockModeConfig {
+    Vault,
+    Freeze,
+}
+
+impl Default for LockModeConfig {
+    fn default() -> Self {
+        LockModeConfig::Vault
+    }
+}
+
+impl From<LockModeConfig> for LockMode {
+    fn from(mode: LockModeConfig) -> Self {
+        match mode {
+            LockModeConfig::Vault => LockMode::Vault,
+            LockModeConfig::Freeze => LockMode::Freeze,
+        }
+    }
+}
+
+/// Reads a TOML file, or a JSON one for any other extension.
+pub fn read<T: DeserializeOwned>(path: &Path) -> Result<T> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("failed to read {}", path.display()))?;
+
+    let parsed = match path.extension().and_then(|ext| ext.to_str()) {
+        Some("toml") => toml::from_str(&content)?,
+        _ => serde_json::from_str(&content)?,
+    };
+
+    Ok(parsed)
+}
+
+/// Pubkeys are written as base58 strings.
+mod from_str {
+    use serde::{de::Error, Deserialize, Deserializer};
+    use std::{fmt::Display, str::FromStr};
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: FromStr,
+        T::Err: Display,
+    {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
+}
+
+mod option_from_str {
+    use serde::{de::Error, Deserialize, Deserializer};
+    use std::{fmt::Display, str::FromStr};
+
+    pub fn deserialize<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+    where
+        D: Deserializer<'de>,
+        T: FromStr,
+        T::Err: Display,
+    {
+        Option::<String>::deserialize(deserializer)?
+            .map(|value| value.parse().map_err(D::Error::custom))
+            .transpose()
+    }
+}
@@ -0,0 +1,266 @@
+//! Manages breed program machines from the command line.
+
+mod config;
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::{bail, Result};
+use breed_client::{accounts, instructions, pda, BreedConfig};
+use clap::{Parser, Subcommand};
+use config::{ConfigUpdate, MachineConfig};
+use solana_client::rpc_client::RpcClient;
+use solana_sdk::{
+    commitment_config::CommitmentConfig,
+    instruction::Instruction,
+    signature::{read_keypair_file, Keypair, Signer},
+    transaction::Transaction,
+};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[clap(version, about)]
+struct Opts {
+    /// Keypair of the machine authority, paying the transactions.
+    #[clap(long, short, global = true)]
+    keypair: Option<PathBuf>,
+
+    /// RPC URL, a local `solana-test-validator` by default.
+    #[clap(long, short, global = true, default_value = "http://127.0.0.1:8899")]
+    url: String,
+
+    /// Simulates the transaction and prints its logs instead of sending it.
+    #[clap(long, global = true)]
+    dry_run: bool,
+
+    #[clap(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Creates a machine from a TOML or JSON config file.
+    CreateMachine { config: PathBuf },
+    /// Updates a machine config from a TOML or JSON file. Missing fields stay
+    /// unchanged.
+    UpdateConfig { machine: Pubkey, config: PathBuf },
+    /// Prints a machine state and config.
+    ShowMachine { machine: Pubkey },
+    /// Lists the breedings in progress of a machine.
+    ListBreeds {
+        machine: Pubkey,
+        /// Only lists the breedings of this wallet.
+        #[clap(long)]
+        owner: Option<Pubkey>,
+    },
+    /// Burns the unclaimed rewards and closes a machine without breedings in
+    /// progress.
+    CloseMachine { machine: Pubkey },
+    /// Mints more whitelist tokens to a machine created with a reward cap.
+    ReplenishRewards { machine: Pubkey, amount: u64 },
+    /// Adds a reward tier with its own whitelist token, minting its whole
+    /// supply.
+    AddRewardTier {
+        machine: Pubkey,
+        /// Odds of the tier relative to the other tiers' weights.
+        #[clap(long)]
+        weight: u16,
+        #[clap(long)]
+        supply: u64,
+    },
+    /// Sets the weight of a reward tier, 0 being the config whitelist token.
+    SetRewardTierWeight {
+        machine: Pubkey,
+        tier: u8,
+        weight: u16,
+    },
+    /// Stops a machine from starting new breedings.
+    Pause { machine: Pubkey },
+    /// Lets a paused machine start new breedings again.
+    Resume { machine: Pubkey },
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::parse();
+    let client = RpcClient::new_with_commitment(opts.url.clone(), CommitmentConfig::confirmed());
+
+    match opts.command {
+        Command::CreateMachine { config: ref path } => {
+            let config: BreedConfig = config::read::<MachineConfig>(path)?.into();
+            let authority = read_keypair(&opts)?;
+
+            let (machine, _) = pda::find_breeding_machine_address(
+                &config.parents_candy_machine,
+                &config.reward_candy_machine,
+                &authority.pubkey(),
+            );
+            println!("Machine: {}", machine);
+
+            let ix = instructions::create_machine(&authority.pubkey(), config);
+            send(&client, &authority, &[ix], opts.dry_run)
+        }
+        Command::UpdateConfig {
+            machine,
+            config: ref path,
+        } => {
+            let args = config::read::<ConfigUpdate>(path)?.into();
+            let authority = read_keypair(&opts)?;
+
+            let ix = instructions::update_machine_config(&machine, &authority.pubkey(), args);
+            send(&client, &authority, &[ix], opts.dry_run)
+        }
+        Command::ShowMachine { machine } => show_machine(&client, &machine),
+        Command::ListBreeds { machine, owner } => list_breeds(&client, &machine, owner),
+        Command::CloseMachine { machine } => {
+            let authority = read_keypair(&opts)?;
+
+            let machine_account = accounts::fetch_machine(&client, &machine)?;
+
+            let ix = instructions::close_machine(&machine, &machine_account, &authority.pubkey());
+            send(&client, &authority, &[ix], opts.dry_run)
+        }
+        Command::ReplenishRewards { machine, amount } => {
+            let authority = read_keypair(&opts)?;
+
+            let ix = instructions::replenish_rewards(&machine, &authority.pubkey(), amount);
+            send(&client, &authority, &[ix], opts.dry_run)
+        }
+        Command::AddRewardTier {
+            machine,
+            weight,
+            supply,
+        } => {
+            let authority = read_keypair(&opts)?;
+            let machine_account = accounts::fetch_machine(&client, &machine)?;
+
+            let ix = instructions::add_reward_tier(
+                &machine,
+                &machine_account,
+                &authority.pubkey(),
+                weight,
+                supply,
+            );
+            send(&client, &authority, &[ix], opts.dry_run)
+        }
+        Command::SetRewardTierWeight {
+            machine,
+            tier,
+            weight,
+        } => {
+            let authority = read_keypair(&opts)?;
+
+            let ix =
+                instructions::set_reward_tier_weight(&machine, &authority.pubkey(), tier, weight);
+            send(&client, &authority, &[ix], opts.dry_run)
+        }
+        Command::Pause { machine } => {
+            let authority = read_keypair(&opts)?;
+
+            let ix = instructions::pause_machine(&machine, &authority.pubkey());
+            send(&client, &authority, &[ix], opts.dry_run)
+        }
+        Command::Resume { machine } => {
+            let authority = read_keypair(&opts)?;
+
+            let ix = instructions::resume_machine(&machine, &authority.pubkey());
+            send(&client, &authority, &[ix], opts.dry_run)
+        }
+    }
+}
+
+/// Reads `--keypair`, or the Solana CLI default keypair.
+fn read_keypair(opts: &Opts) -> Result<Keypair> {
+    let path = match &opts.keypair {
+        Some(path) => path.clone(),
+        None => PathBuf::from(std::env::var("HOME")?).join(".config/solana/id.json"),
+    };
+
+    read_keypair_file(&path)
+        .map_err(|e| anyhow::anyhow!("failed to read keypair {}: {}", path.display(), e))
+}
+
+fn send(client: &RpcClient, payer: &Keypair, ixs: &[Instruction], dry_run: bool) -> Result<()> {
+    let blockhash = client.get_latest_blockhash()?;
+    let tx = Transaction::new_signed_with_payer(ixs, Some(&payer.pubkey()), &[payer], blockhash);
+
+    if dry_run {
+        let result = client.simulate_transaction(&tx)?.value;
+        for log in result.logs.unwrap_or_default() {
+            println!("{}", log);
+        }
+
+        if let Some(err) = result.err {
+            bail!("Simulation failed: {}", err);
+        }
+
+        println!("Simulation succeeded, the transaction wasn't sent.");
+        return Ok(());
+    }
+
+    let signature = client.send_and_confirm_transaction_with_spinner(&tx)?;
+    println!("Signature: {}", signature);
+
+    Ok(())
+}
+
+fn show_machine(client: &RpcClient, address: &Pubkey) -> Result<()> {
+    let machine = accounts::fetch_machine(client, address)?;
+    let rewards_left = client
+        .get_token_account_balance(&pda::whitelist_vault_address(address))
+        .map(|balance| balance.amount)
+        .unwrap_or_else(|_| "none, the vault is closed".to_string());
+
+    println!("Machine: {}", address);
+    println!("Authority: {}", machine.authority);
+    if let Some(pending_authority) = machine.pending_authority {
+        println!("Pending authority: {}", pending_authority);
+    }
+    println!("Creator: {}", machine.creator);
+    println!("Paused: {}", machine.paused);
+    println!("Bred: {}", machine.bred);
+    println!("Born: {}", machine.born);
+    println!("Reserved: {}", machine.reserved);
+    println!("In progress: {}", machine.in_progress);
+    println!("Rewards left: {}", rewards_left);
+    println!("Reward tier 0 weight: {}", machine.base_reward_weight);
+    for (i, tier) in machine.extra_reward_tiers.iter().enumerate() {
+        let tier_number = i as u8 + 1;
+        let left = client
+            .get_token_account_balance(&pda::reward_tier_vault_address(address, tier_number))
+            .map(|balance| balance.amount)
+            .unwrap_or_else(|_| "none, the vault is closed".to_string());
+        println!(
+            "Reward tier {} weight: {}, rewards left: {}",
+            tier_number, tier.weight, left
+        );
+    }
+    println!("Child NFT: {:#?}", machine.child_nft);
+    println!("Config: {:#?}", machine.config);
+
+    Ok(())
+}
+
+fn list_breeds(client: &RpcClient, machine: &Pubkey, owner: Option<Pubkey>) -> Result<()> {
+    let breeds = accounts::fetch_machine_breeds(client, machine)?;
+
+    for (address, breed_data) in breeds {
+        if owner.map_or(false, |owner| owner != breed_data.owner) {
+            continue;
+        }
+
+        println!("Breed data: {}", address);
+        println!("  Owner: {}", breed_data.owner);
+        for mint in &breed_data.mints {
+            println!("  Parent: {}", mint);
+        }
+        println!("  Started at: {}", breed_data.timestamp);
+        println!(
+            "  Ready at: {}",
+            breed_data.timestamp + breed_data.breeding_time as i64
+        );
+        println!("  Lock mode: {:?}", breed_data.lock_mode);
+        println!("  Success odds: {} bps", breed_data.success_bps);
+        println!("  Fee paid: {}", breed_data.fee_paid);
+        println!("  SOL fee paid: {}", breed_data.sol_fee_paid);
+    }
+
+    Ok(())
+}
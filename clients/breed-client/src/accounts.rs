@@ -0,0 +1,111 @@
+use crate::{pda::find_breed_data_address, ClientError, Result};
+use anchor_lang::{prelude::Pubkey, AccountDeserialize, Discriminator};
+use breed_program::{BreedData, BreedMachine, Lineage, ParentRecord};
+use solana_account_decoder::UiAccountEncoding;
+use solana_client::{
+    rpc_client::RpcClient,
+    rpc_config::{RpcAccountInfoConfig, RpcProgramAccountsConfig},
+    rpc_filter::{Memcmp, MemcmpEncodedBytes, RpcFilterType},
+};
+
+/// Decodes an account of the program, checking its discriminator.
+pub fn decode<T: AccountDeserialize>(address: &Pubkey, data: &[u8]) -> Result<T> {
+    T::try_deserialize(&mut &data[..]).map_err(|e| ClientError::Decode(*address, e))
+}
+
+/// Fetches and decodes an account of the program.
+pub fn fetch<T: AccountDeserialize>(client: &RpcClient, address: &Pubkey) -> Result<T> {
+    fetch_optional(client, address)?.ok_or(ClientError::AccountNotFound(*address))
+}
+
+/// Fetches and decodes an account of the program, None when it doesn't exist.
+pub fn fetch_optional<T: AccountDeserialize>(
+    client: &RpcClient,
+    address: &Pubkey,
+) -> Result<Option<T>> {
+    let account = client
+        .get_account_with_commitment(address, client.commitment())?
+        .value;
+
+    account
+        .map(|account| decode(address, &account.data))
+        .transpose()
+}
+
+pub fn fetch_machine(client: &RpcClient, address: &Pubkey) -> Result<BreedMachine> {
+    fetch(client, address)
+}
+
+pub fn fetch_breed_data(client: &RpcClient, address: &Pubkey) -> Result<BreedData> {
+    fetch(client, address)
+}
+
+pub fn fetch_parent_record(client: &RpcClient, address: &Pubkey) -> Result<Option<ParentRecord>> {
+    fetch_optional(client, address)
+}
+
+pub fn fetch_lineage(client: &RpcClient, address: &Pubkey) -> Result<Lineage> {
+    fetch(client, address)
+}
+
+/// Fetches all the program accounts of type `T` taking `space` bytes, the
+/// discriminator included. Accounts of the same type in an older layout have
+/// another size, so they're left out.
+pub fn fetch_all<T: AccountDeserialize + Discriminator>(
+    client: &RpcClient,
+    space: usize,
+) -> Result<Vec<(Pubkey, T)>> {
+    let config = RpcProgramAccountsConfig {
+        filters: Some(vec![
+            RpcFilterType::DataSize(space as u64),
+            RpcFilterType::Memcmp(Memcmp {
+                offset: 0,
+                bytes: MemcmpEncodedBytes::Base58(bs58::encode(T::discriminator()).into_string()),
+                encoding: None,
+            }),
+        ]),
+        account_config: RpcAccountInfoConfig {
+            encoding: Some(UiAccountEncoding::Base64),
+            commitment: Some(client.commitment()),
+            ..RpcAccountInfoConfig::default()
+        },
+        ..RpcProgramAccountsConfig::default()
+    };
+
+    let accounts = client
+        .get_program_accounts_with_config(&breed_program::ID, config)?
+        .into_iter()
+        .map(|(address, account)| (address, account.data))
+        .collect();
+
+    decode_all(accounts, space)
+}
+
+/// Decodes the accounts of type `T` taking `space` bytes, leaving out the
+/// other ones, like the filters of `fetch_all`.
+pub fn decode_all<T: AccountDeserialize + Discriminator>(
+    accounts: Vec<(Pubkey, Vec<u8>)>,
+    space: usize,
+) -> Result<Vec<(Pubkey, T)>> {
+    accounts
+        .into_iter()
+        .filter(|(_, data)| data.len() == space && data.starts_with(&T::discriminator()))
+        .map(|(address, data)| Ok((address, decode(&address, &data)?)))
+        .collect()
+}
+
+/// Fetches the breedings in progress of a machine. Breed data accounts don't
+/// store their machine, so they're matched by address.
+pub fn fetch_machine_breeds(
+    client: &RpcClient,
+    machine: &Pubkey,
+) -> Result<Vec<(Pubkey, BreedData)>> {
+    let breeds = fetch_all::<BreedData>(client, 8 + BreedData::LEN)?
+        .into_iter()
+        .filter(|(address, breed_data)| {
+            find_breed_data_address(machine, &breed_data.mints).0 == *address
+        })
+        .collect();
+
+    Ok(breeds)
+}
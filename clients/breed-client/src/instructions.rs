@@ -0,0 +1,627 @@
+use crate::pda::{
+    find_breed_data_address, find_breed_lineage_address, find_breeding_machine_address,
+    find_child_mint_address, find_legacy_breed_data_address, find_lineage_address,
+    find_parent_record_address, find_reward_tier_token_address, find_whitelist_token_address,
+    reward_tier_vault_address, sort_parents, whitelist_vault_address,
+};
+use anchor_lang::{
+    prelude::Pubkey,
+    solana_program::{
+        instruction::{AccountMeta, Instruction},
+        system_program, sysvar,
+    },
+    InstructionData, ToAccountMetas,
+};
+use anchor_spl::associated_token::get_associated_token_address;
+use breed_program::{
+    accounts, instruction, instructions::UpdateMachineConfigArgs, BreedConfig, BreedData,
+    BreedMachine, ChildNft, FeeMode, LockMode, ParentCollection, MAX_PARENT_COLLECTIONS,
+};
+use mpl_token_metadata::pda::{find_master_edition_account, find_metadata_account};
+
+fn build(accounts: impl ToAccountMetas, data: impl InstructionData) -> Instruction {
+    Instruction {
+        program_id: breed_program::ID,
+        accounts: accounts.to_account_metas(None),
+        data: data.data(),
+    }
+}
+
+/// Creates the user ATA of `mint`, e.g. the fee payer ATA or the ATA receiving
+/// the reward whitelist token, which the program expects to exist.
+pub fn create_associated_token_account(
+    payer: &Pubkey,
+    owner: &Pubkey,
+    mint: &Pubkey,
+) -> Instruction {
+    spl_associated_token_account::create_associated_token_account(payer, owner, mint)
+}
+
+/// Creates a machine with `authority` as creator, and mints the reward
+/// whitelist supply to its vault.
+pub fn create_machine(authority: &Pubkey, config: BreedConfig) -> Instruction {
+    let (breeding_machine, _) = find_breeding_machine_address(
+        &config.parents_candy_machine,
+        &config.reward_candy_machine,
+        authority,
+    );
+
+    build(
+        accounts::InitializeBreedMachine {
+            breeding_machine,
+            whitelist_token: find_whitelist_token_address(&breeding_machine).0,
+            whitelist_vault: whitelist_vault_address(&breeding_machine),
+            authority: *authority,
+            rent: sysvar::rent::ID,
+            token_program: anchor_spl::token::ID,
+            system_program: system_program::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+        },
+        instruction::CreateMachine { config },
+    )
+}
+
+pub fn update_machine_config(
+    machine: &Pubkey,
+    authority: &Pubkey,
+    args: UpdateMachineConfigArgs,
+) -> Instruction {
+    build(
+        accounts::UpdateMachineConfig {
+            breeding_machine: *machine,
+            authority: *authority,
+        },
+        instruction::UpdateMachineConfig { args },
+    )
+}
+
+pub fn set_parent_collections(
+    machine: &Pubkey,
+    authority: &Pubkey,
+    extra_parent_collections: Vec<ParentCollection>,
+    compatibility: Option<[u8; MAX_PARENT_COLLECTIONS]>,
+) -> Instruction {
+    build(
+        accounts::UpdateMachineConfig {
+            breeding_machine: *machine,
+            authority: *authority,
+        },
+        instruction::SetParentCollections {
+            extra_parent_collections,
+            compatibility,
+        },
+    )
+}
+
+pub fn set_child_nft(
+    machine: &Pubkey,
+    authority: &Pubkey,
+    child_nft: Option<ChildNft>,
+) -> Instruction {
+    build(
+        accounts::UpdateMachineConfig {
+            breeding_machine: *machine,
+            authority: *authority,
+        },
+        instruction::SetChildNft { child_nft },
+    )
+}
+
+/// Mints `amount` more whitelist tokens to the vault of a `mint_capped`
+/// machine.
+pub fn replenish_rewards(machine: &Pubkey, authority: &Pubkey, amount: u64) -> Instruction {
+    build(
+        accounts::ReplenishRewards {
+            breeding_machine: *machine,
+            whitelist_token: find_whitelist_token_address(machine).0,
+            whitelist_vault: whitelist_vault_address(machine),
+            authority: *authority,
+            token_program: anchor_spl::token::ID,
+        },
+        instruction::ReplenishRewards { amount },
+    )
+}
+
+/// Adds the next reward tier, minting its whole supply to its vault.
+pub fn add_reward_tier(
+    machine: &Pubkey,
+    machine_account: &BreedMachine,
+    authority: &Pubkey,
+    weight: u16,
+    supply: u64,
+) -> Instruction {
+    let tier = machine_account.extra_reward_tiers.len() as u8 + 1;
+
+    build(
+        accounts::AddRewardTier {
+            breeding_machine: *machine,
+            tier_token: find_reward_tier_token_address(machine, tier).0,
+            tier_vault: reward_tier_vault_address(machine, tier),
+            authority: *authority,
+            rent: sysvar::rent::ID,
+            token_program: anchor_spl::token::ID,
+            system_program: system_program::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+        },
+        instruction::AddRewardTier {
+            tier,
+            weight,
+            supply,
+        },
+    )
+}
+
+/// Tier 0 is the config whitelist token.
+pub fn set_reward_tier_weight(
+    machine: &Pubkey,
+    authority: &Pubkey,
+    tier: u8,
+    weight: u16,
+) -> Instruction {
+    build(
+        accounts::UpdateMachineConfig {
+            breeding_machine: *machine,
+            authority: *authority,
+        },
+        instruction::SetRewardTierWeight { tier, weight },
+    )
+}
+
+pub fn pause_machine(machine: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::ToggleMachine {
+            breeding_machine: *machine,
+            authority: *authority,
+        },
+        instruction::PauseMachine {},
+    )
+}
+
+pub fn resume_machine(machine: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::ToggleMachine {
+            breeding_machine: *machine,
+            authority: *authority,
+        },
+        instruction::ResumeMachine {},
+    )
+}
+
+pub fn propose_authority(
+    machine: &Pubkey,
+    authority: &Pubkey,
+    new_authority: &Pubkey,
+) -> Instruction {
+    build(
+        accounts::ProposeAuthority {
+            breeding_machine: *machine,
+            authority: *authority,
+        },
+        instruction::ProposeAuthority {
+            new_authority: *new_authority,
+        },
+    )
+}
+
+pub fn accept_authority(machine: &Pubkey, new_authority: &Pubkey) -> Instruction {
+    build(
+        accounts::AcceptAuthority {
+            breeding_machine: *machine,
+            new_authority: *new_authority,
+        },
+        instruction::AcceptAuthority {},
+    )
+}
+
+/// Burns the unclaimed whitelist tokens of every reward tier and closes the
+/// machine. Fails while breedings are in progress.
+pub fn close_machine(
+    machine: &Pubkey,
+    machine_account: &BreedMachine,
+    authority: &Pubkey,
+) -> Instruction {
+    let mut ix = build(
+        accounts::CloseMachine {
+            breeding_machine: *machine,
+            whitelist_token: find_whitelist_token_address(machine).0,
+            whitelist_vault: whitelist_vault_address(machine),
+            authority: *authority,
+            token_program: anchor_spl::token::ID,
+        },
+        instruction::CloseMachine {},
+    );
+    ix.accounts
+        .extend(reward_tier_accounts(machine, machine_account, None));
+
+    ix
+}
+
+pub fn migrate_machine(machine: &Pubkey, authority: &Pubkey) -> Instruction {
+    build(
+        accounts::MigrateMachine {
+            breeding_machine: *machine,
+            authority: *authority,
+            system_program: system_program::ID,
+        },
+        instruction::MigrateMachine {},
+    )
+}
+
+/// Finalizes a breeding started before the machine was migrated, sending a
+/// config whitelist token to the user. The user whitelist ATA must exist.
+/// `mint_a` and `mint_b` are in the order the legacy breeding locked them.
+pub fn finalize_legacy_breeding(
+    machine: &Pubkey,
+    user: &Pubkey,
+    mint_a: &Pubkey,
+    mint_b: &Pubkey,
+) -> Instruction {
+    let (breed_data, _) = find_legacy_breed_data_address(machine, mint_a, mint_b);
+    let (whitelist_token, _) = find_whitelist_token_address(machine);
+
+    build(
+        accounts::FinalizeLegacyBreeding {
+            breeding_machine: *machine,
+            breed_data,
+            mint_parent_a: *mint_a,
+            mint_parent_b: *mint_b,
+            user_ata_parent_a: get_associated_token_address(user, mint_a),
+            user_ata_parent_b: get_associated_token_address(user, mint_b),
+            vault_ata_parent_a: get_associated_token_address(&breed_data, mint_a),
+            vault_ata_parent_b: get_associated_token_address(&breed_data, mint_b),
+            whitelist_token,
+            whitelist_vault: whitelist_vault_address(machine),
+            user_whitelist_ata: get_associated_token_address(user, &whitelist_token),
+            user_wallet: *user,
+            token_program: anchor_spl::token::ID,
+        },
+        instruction::FinalizeLegacyBreeding {},
+    )
+}
+
+/// Returns the parents of a breeding started before the machine was migrated.
+/// `mint_a` and `mint_b` are in the order the legacy breeding locked them.
+pub fn cancel_legacy_breeding(
+    machine: &Pubkey,
+    user: &Pubkey,
+    mint_a: &Pubkey,
+    mint_b: &Pubkey,
+) -> Instruction {
+    let (breed_data, _) = find_legacy_breed_data_address(machine, mint_a, mint_b);
+
+    build(
+        accounts::CancelLegacyBreeding {
+            breeding_machine: *machine,
+            breed_data,
+            mint_parent_a: *mint_a,
+            mint_parent_b: *mint_b,
+            user_ata_parent_a: get_associated_token_address(user, mint_a),
+            user_ata_parent_b: get_associated_token_address(user, mint_b),
+            vault_ata_parent_a: get_associated_token_address(&breed_data, mint_a),
+            vault_ata_parent_b: get_associated_token_address(&breed_data, mint_b),
+            user_wallet: *user,
+            token_program: anchor_spl::token::ID,
+        },
+        instruction::CancelLegacyBreeding {},
+    )
+}
+
+/// Locks the parents and charges the fees of the machine config. The fee
+/// payer ATA must exist when the machine charges a token fee.
+pub fn initialize_breeding(
+    machine: &Pubkey,
+    machine_account: &BreedMachine,
+    user: &Pubkey,
+    mints: &[Pubkey],
+) -> Instruction {
+    let parents = sort_parents(mints);
+    let (breed_data, _) = find_breed_data_address(machine, &parents);
+    let config = &machine_account.config;
+
+    // Fee accounts the machine config doesn't use are filled with the user
+    // wallet.
+    let (fee_token, fee_payer_ata, fee_vault) = if config.initialization_fee_price > 0 {
+        let fee_token = config.initialization_fee_token;
+        (
+            fee_token,
+            get_associated_token_address(user, &fee_token),
+            get_associated_token_address(&breed_data, &fee_token),
+        )
+    } else {
+        (*user, *user, *user)
+    };
+
+    let mut ix = build(
+        accounts::InitializeBreed {
+            breeding_machine: *machine,
+            breed_data,
+            fee_token,
+            fee_payer_ata,
+            fee_vault,
+            whitelist_token: find_whitelist_token_address(machine).0,
+            whitelist_vault: whitelist_vault_address(machine),
+            user_wallet: *user,
+            rent: sysvar::rent::ID,
+            token_program: anchor_spl::token::ID,
+            associated_token_program: anchor_spl::associated_token::ID,
+            system_program: system_program::ID,
+            token_metadata_program: mpl_token_metadata::ID,
+        },
+        instruction::InitializeBreeding {
+            parents: parents.clone(),
+        },
+    );
+    ix.accounts.extend(parent_accounts(
+        &parents,
+        user,
+        &breed_data,
+        config.lock_mode,
+        ParentAccountsLen::Init,
+    ));
+    ix.accounts
+        .extend(reward_tier_accounts(machine, machine_account, None));
+
+    ix
+}
+
+/// Stores the outcome seed of a breeding, so it can be finalized after its
+/// reveal slot left the `SlotHashes` sysvar. Anyone can send it.
+pub fn reveal_breeding(machine: &Pubkey, mints: &[Pubkey]) -> Instruction {
+    build(
+        accounts::RevealBreeding {
+            breed_data: find_breed_data_address(machine, mints).0,
+            slot_hashes: sysvar::slot_hashes::ID,
+        },
+        instruction::RevealBreeding {},
+    )
+}
+
+/// Unlocks the parents, settles the fees and rewards the user, with a
+/// whitelist token or the child NFT. The user whitelist ATA must exist when
+/// the machine sends whitelist tokens.
+pub fn finalize_breeding(
+    machine: &Pubkey,
+    machine_account: &BreedMachine,
+    breed_data_account: &BreedData,
+    user: &Pubkey,
+) -> Instruction {
+    let (breed_data, _) = find_breed_data_address(machine, &breed_data_account.mints);
+    let fees = FeeAccounts::new(&breed_data, breed_data_account, user);
+    let whitelist_token = find_whitelist_token_address(machine).0;
+
+    // Child accounts are filled with the user wallet when the machine sends
+    // whitelist tokens.
+    let reward_accounts = match &machine_account.child_nft {
+        Some(child_nft) => {
+            let (child_mint, _) = find_child_mint_address(machine, machine_account.born);
+
+            accounts::FinalizeBreeding {
+                user_whitelist_ata: *user,
+                child_mint,
+                child_metadata: find_metadata_account(&child_mint).0,
+                child_master_edition: find_master_edition_account(&child_mint).0,
+                child_ata: get_associated_token_address(user, &child_mint),
+                collection_mint: child_nft.collection,
+                collection_metadata: find_metadata_account(&child_nft.collection).0,
+                collection_master_edition: find_master_edition_account(&child_nft.collection).0,
+                child_parent_record: find_parent_record_address(&child_mint).0,
+                lineage: find_lineage_address(&child_mint).0,
+                ..finalize_accounts(machine, &breed_data, &fees, user)
+            }
+        }
+        None => accounts::FinalizeBreeding {
+            user_whitelist_ata: get_associated_token_address(user, &whitelist_token),
+            lineage: find_breed_lineage_address(machine, machine_account.born).0,
+            ..finalize_accounts(machine, &breed_data, &fees, user)
+        },
+    };
+
+    let mut ix = build(reward_accounts, instruction::FinalizeBreeding {});
+    ix.accounts.extend(parent_accounts(
+        &breed_data_account.mints,
+        user,
+        &breed_data,
+        breed_data_account.lock_mode,
+        ParentAccountsLen::Finalize,
+    ));
+    ix.accounts
+        .extend(reward_tier_accounts(machine, machine_account, Some(user)));
+
+    ix
+}
+
+/// Mint and vault of each extra reward tier, then the user ATA on finalize,
+/// which the program creates when missing. Initialize reads the vaults to
+/// reserve a whitelist token.
+fn reward_tier_accounts(
+    machine: &Pubkey,
+    machine_account: &BreedMachine,
+    user: Option<&Pubkey>,
+) -> Vec<AccountMeta> {
+    let mut accounts = vec![];
+    for tier in 1..=machine_account.extra_reward_tiers.len() as u8 {
+        let mint = find_reward_tier_token_address(machine, tier).0;
+        accounts.push(AccountMeta::new(mint, false));
+        accounts.push(AccountMeta::new(
+            reward_tier_vault_address(machine, tier),
+            false,
+        ));
+        if let Some(user) = user {
+            accounts.push(AccountMeta::new(
+                get_associated_token_address(user, &mint),
+                false,
+            ));
+        }
+    }
+
+    accounts
+}
+
+fn finalize_accounts(
+    machine: &Pubkey,
+    breed_data: &Pubkey,
+    fees: &FeeAccounts,
+    user: &Pubkey,
+) -> accounts::FinalizeBreeding {
+    accounts::FinalizeBreeding {
+        breeding_machine: *machine,
+        breed_data: *breed_data,
+        whitelist_token: find_whitelist_token_address(machine).0,
+        whitelist_vault: whitelist_vault_address(machine),
+        user_whitelist_ata: *user,
+        child_mint: *user,
+        child_metadata: *user,
+        child_master_edition: *user,
+        child_ata: *user,
+        collection_mint: *user,
+        collection_metadata: *user,
+        collection_master_edition: *user,
+        child_parent_record: *user,
+        lineage: *user,
+        token_metadata_program: mpl_token_metadata::ID,
+        fee_token: fees.fee_token,
+        fee_vault: fees.fee_vault,
+        fee_treasury: fees.fee_treasury,
+        sol_fee_treasury: fees.sol_fee_treasury,
+        user_wallet: *user,
+        rent: sysvar::rent::ID,
+        token_program: anchor_spl::token::ID,
+        system_program: system_program::ID,
+        associated_token_program: anchor_spl::associated_token::ID,
+        slot_hashes: sysvar::slot_hashes::ID,
+    }
+}
+
+/// Returns the parents to the user and refunds the fees with the cancel
+/// policy the breeding started with. Breedings rolling their outcome can only
+/// be cancelled before their reveal slot.
+pub fn cancel_breeding(
+    machine: &Pubkey,
+    machine_account: &BreedMachine,
+    breed_data_account: &BreedData,
+    user: &Pubkey,
+) -> Instruction {
+    let (breed_data, _) = find_breed_data_address(machine, &breed_data_account.mints);
+    let fees = FeeAccounts::new(&breed_data, breed_data_account, user);
+
+    let mut ix = build(
+        accounts::CancelBreeding {
+            breeding_machine: *machine,
+            breed_data,
+            fee_token: fees.fee_token,
+            fee_vault: fees.fee_vault,
+            fee_payer_ata: fees.fee_payer_ata,
+            fee_treasury: fees.fee_treasury,
+            sol_fee_treasury: fees.sol_fee_treasury,
+            user_wallet: *user,
+            token_program: anchor_spl::token::ID,
+            token_metadata_program: mpl_token_metadata::ID,
+        },
+        instruction::CancelBreeding {},
+    );
+    ix.accounts.extend(parent_accounts(
+        &breed_data_account.mints,
+        user,
+        &breed_data,
+        breed_data_account.lock_mode,
+        ParentAccountsLen::Cancel,
+    ));
+
+    ix
+}
+
+/// How many remaining accounts each instruction takes per parent, see
+/// `ParentAccounts` in the program.
+#[derive(Clone, Copy)]
+enum ParentAccountsLen {
+    Init,
+    Finalize,
+    Cancel,
+}
+
+impl ParentAccountsLen {
+    fn len(self) -> usize {
+        use breed_program::instructions::ParentAccounts;
+
+        match self {
+            ParentAccountsLen::Init => ParentAccounts::INIT_LEN,
+            ParentAccountsLen::Finalize => ParentAccounts::FINALIZE_LEN,
+            ParentAccountsLen::Cancel => ParentAccounts::CANCEL_LEN,
+        }
+    }
+}
+
+/// Returns the remaining accounts of the parents: mint, user ATA, lock
+/// account, parent record and metadata. The lock account is the breed data
+/// ATA in vault mode and the master edition in freeze mode.
+fn parent_accounts(
+    parents: &[Pubkey],
+    user: &Pubkey,
+    breed_data: &Pubkey,
+    lock_mode: LockMode,
+    len: ParentAccountsLen,
+) -> Vec<AccountMeta> {
+    parents
+        .iter()
+        .flat_map(|mint| {
+            let lock_account = match lock_mode {
+                LockMode::Vault => {
+                    AccountMeta::new(get_associated_token_address(breed_data, mint), false)
+                }
+                LockMode::Freeze => {
+                    AccountMeta::new_readonly(find_master_edition_account(mint).0, false)
+                }
+            };
+
+            vec![
+                AccountMeta::new(*mint, false),
+                AccountMeta::new(get_associated_token_address(user, mint), false),
+                lock_account,
+                AccountMeta::new(find_parent_record_address(mint).0, false),
+                AccountMeta::new_readonly(find_metadata_account(mint).0, false),
+            ]
+            .into_iter()
+            .take(len.len())
+        })
+        .collect()
+}
+
+/// Accounts settling the fees escrowed by a breeding. Accounts the breeding
+/// doesn't use are filled with the user wallet.
+struct FeeAccounts {
+    fee_token: Pubkey,
+    fee_vault: Pubkey,
+    fee_payer_ata: Pubkey,
+    fee_treasury: Pubkey,
+    sol_fee_treasury: Pubkey,
+}
+
+impl FeeAccounts {
+    fn new(breed_data: &Pubkey, breed_data_account: &BreedData, user: &Pubkey) -> Self {
+        let mut fees = FeeAccounts {
+            fee_token: *user,
+            fee_vault: *user,
+            fee_payer_ata: *user,
+            fee_treasury: *user,
+            sol_fee_treasury: *user,
+        };
+
+        if breed_data_account.fee_paid > 0 {
+            let fee_token = breed_data_account.fee_token;
+
+            fees.fee_token = fee_token;
+            fees.fee_vault = get_associated_token_address(breed_data, &fee_token);
+            fees.fee_payer_ata = get_associated_token_address(user, &fee_token);
+
+            if breed_data_account.fee_mode != FeeMode::Burn {
+                fees.fee_treasury = breed_data_account.fee_treasury;
+            }
+        }
+
+        if breed_data_account.sol_fee_paid > 0 {
+            fees.sol_fee_treasury = breed_data_account.sol_fee_treasury;
+        }
+
+        fees
+    }
+}
@@ -0,0 +1,27 @@
+//! Rust client for the breed program: PDA derivation, instruction builders and
+//! account fetching.
+//!
+//! Instruction builders only derive addresses, they don't send anything. The
+//! ones depending on the machine or breeding state take the decoded accounts,
+//! fetched with the helpers in [`accounts`].
+
+pub mod accounts;
+pub mod instructions;
+pub mod pda;
+
+pub use breed_program::{
+    self, instructions::UpdateMachineConfigArgs, BreedConfig, BreedData, BreedMachine, Lineage,
+    ParentRecord, ID as PROGRAM_ID,
+};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ClientError {
+    #[error(transparent)]
+    Rpc(#[from] solana_client::client_error::ClientError),
+    #[error("account {0} not found")]
+    AccountNotFound(anchor_lang::prelude::Pubkey),
+    #[error("failed to decode account {0}: {1}")]
+    Decode(anchor_lang::prelude::Pubkey, anchor_lang::error::Error),
+}
+
+pub type Result<T> = std::result::Result<T, ClientError>;
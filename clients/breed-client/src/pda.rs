@@ -0,0 +1,113 @@
+use anchor_lang::prelude::Pubkey;
+use anchor_spl::associated_token::get_associated_token_address;
+use breed_program::{
+    instructions::CHILD_MINT_PREFIX, BreedData, BreedMachine, Lineage, ParentRecord,
+};
+
+/// The machine address is derived from the wallet that created it, which stays
+/// the same after the authority is transferred.
+pub fn find_breeding_machine_address(
+    parents_candy_machine: &Pubkey,
+    reward_candy_machine: &Pubkey,
+    creator: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            BreedMachine::PREFIX,
+            parents_candy_machine.as_ref(),
+            reward_candy_machine.as_ref(),
+            creator.as_ref(),
+        ],
+        &breed_program::ID,
+    )
+}
+
+/// The program expects parents sorted by mint address, so each set of parents
+/// has a single breed data account.
+pub fn sort_parents(mints: &[Pubkey]) -> Vec<Pubkey> {
+    let mut parents = mints.to_vec();
+    parents.sort();
+    parents
+}
+
+/// Parents are locked while breeding, so the breed data is derived from the
+/// first sorted parent only.
+pub fn find_breed_data_address(machine: &Pubkey, mints: &[Pubkey]) -> (Pubkey, u8) {
+    let parents = sort_parents(mints);
+
+    Pubkey::find_program_address(
+        &[
+            BreedData::PREFIX,
+            machine.as_ref(),
+            BreedData::seed_mint(&parents).as_ref(),
+        ],
+        &breed_program::ID,
+    )
+}
+
+/// Breed data of a breeding started before its machine was migrated, derived
+/// from both parents in the order they were locked.
+pub fn find_legacy_breed_data_address(
+    machine: &Pubkey,
+    mint_a: &Pubkey,
+    mint_b: &Pubkey,
+) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[
+            BreedData::PREFIX,
+            machine.as_ref(),
+            mint_a.as_ref(),
+            mint_b.as_ref(),
+        ],
+        &breed_program::ID,
+    )
+}
+
+pub fn find_whitelist_token_address(machine: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[b"whitelist_token", machine.as_ref()], &breed_program::ID)
+}
+
+/// The machine ATA holding the reward whitelist tokens.
+pub fn whitelist_vault_address(machine: &Pubkey) -> Pubkey {
+    get_associated_token_address(machine, &find_whitelist_token_address(machine).0)
+}
+
+/// Whitelist token of an extra reward tier, numbered from 1.
+pub fn find_reward_tier_token_address(machine: &Pubkey, tier: u8) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[b"whitelist_token", machine.as_ref(), &[tier]],
+        &breed_program::ID,
+    )
+}
+
+/// The machine ATA holding the whitelist tokens of an extra reward tier.
+pub fn reward_tier_vault_address(machine: &Pubkey, tier: u8) -> Pubkey {
+    get_associated_token_address(machine, &find_reward_tier_token_address(machine, tier).0)
+}
+
+pub fn find_parent_record_address(mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[ParentRecord::PREFIX, mint.as_ref()], &breed_program::ID)
+}
+
+/// Children minted on finalize are derived from the machine `born` counter
+/// when they are minted.
+pub fn find_child_mint_address(machine: &Pubkey, born: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[CHILD_MINT_PREFIX, machine.as_ref(), &born.to_le_bytes()],
+        &breed_program::ID,
+    )
+}
+
+/// Lineage of a child NFT minted on finalize.
+pub fn find_lineage_address(child_mint: &Pubkey) -> (Pubkey, u8) {
+    Pubkey::find_program_address(&[Lineage::PREFIX, child_mint.as_ref()], &breed_program::ID)
+}
+
+/// Lineage of a breeding rewarded with a whitelist token, derived from the
+/// machine `born` counter when it was finalized.
+pub fn find_breed_lineage_address(machine: &Pubkey, breed_id: u64) -> (Pubkey, u8) {
+    Pubkey::find_program_address(
+        &[Lineage::PREFIX, machine.as_ref(), &breed_id.to_le_bytes()],
+        &breed_program::ID,
+    )
+}
@@ -0,0 +1,194 @@
+use crate::{BreedConfig, ChildNft, ParentCollection, MAX_PARENT_COLLECTIONS};
+use anchor_lang::prelude::*;
+
+#[event]
+pub struct MachineCreated {
+    pub machine: Pubkey,
+    pub authority: Pubkey,
+    pub creator: Pubkey,
+    pub parents_candy_machine: Pubkey,
+    pub reward_candy_machine: Pubkey,
+    pub reward_supply: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ConfigUpdated {
+    pub machine: Pubkey,
+    pub authority: Pubkey,
+    // The config after the update.
+    pub config: BreedConfig,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ParentCollectionsUpdated {
+    pub machine: Pubkey,
+    pub authority: Pubkey,
+    pub extra_parent_collections: Vec<ParentCollection>,
+    pub compatibility: Option<[u8; MAX_PARENT_COLLECTIONS]>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct ChildNftUpdated {
+    pub machine: Pubkey,
+    pub authority: Pubkey,
+    // None when the machine went back to whitelist tokens.
+    pub child_nft: Option<ChildNft>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardTierAdded {
+    pub machine: Pubkey,
+    pub authority: Pubkey,
+    pub tier: u8,
+    pub weight: u16,
+    pub supply: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardTierWeightUpdated {
+    pub machine: Pubkey,
+    pub authority: Pubkey,
+    pub tier: u8,
+    pub weight: u16,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MachinePaused {
+    pub machine: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MachineResumed {
+    pub machine: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityProposed {
+    pub machine: Pubkey,
+    pub authority: Pubkey,
+    pub pending_authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct AuthorityAccepted {
+    pub machine: Pubkey,
+    pub previous_authority: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MachineClosed {
+    pub machine: Pubkey,
+    pub authority: Pubkey,
+    // Whitelist tokens burned, across all the reward tiers.
+    pub burned: u64,
+    pub bred: u64,
+    pub born: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct MachineMigrated {
+    pub machine: Pubkey,
+    pub authority: Pubkey,
+    pub bred: u64,
+    pub born: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct RewardsReplenished {
+    pub machine: Pubkey,
+    pub authority: Pubkey,
+    pub amount: u64,
+    // The reward supply after the replenishment.
+    pub reward_supply: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BreedingStarted {
+    pub machine: Pubkey,
+    pub breed_data: Pubkey,
+    pub owner: Pubkey,
+    pub parents: Vec<Pubkey>,
+    pub fee_paid: u64,
+    pub sol_fee_paid: u64,
+    pub in_progress: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BreedingRevealed {
+    pub breed_data: Pubkey,
+    pub owner: Pubkey,
+    pub seed: [u8; 32],
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BreedingFinalized {
+    pub machine: Pubkey,
+    pub breed_data: Pubkey,
+    pub owner: Pubkey,
+    pub parents: Vec<Pubkey>,
+    pub parents_burned: bool,
+    // False when the outcome roll failed and no child was born.
+    pub succeeded: bool,
+    // None when the machine sent a reward whitelist token, or no child was born.
+    pub child_mint: Option<Pubkey>,
+    // Not created when no child was born.
+    pub lineage: Pubkey,
+    // Tier of the whitelist token sent, None when no token was sent.
+    pub reward_tier: Option<u8>,
+    pub generation: u16,
+    pub bred: u64,
+    pub born: u64,
+    pub in_progress: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LegacyBreedingFinalized {
+    pub machine: Pubkey,
+    pub breed_data: Pubkey,
+    pub owner: Pubkey,
+    pub parents: Vec<Pubkey>,
+    pub parents_burned: bool,
+    pub bred: u64,
+    pub born: u64,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct LegacyBreedingCancelled {
+    pub machine: Pubkey,
+    pub breed_data: Pubkey,
+    pub owner: Pubkey,
+    pub parents: Vec<Pubkey>,
+    pub timestamp: i64,
+}
+
+#[event]
+pub struct BreedingCancelled {
+    pub machine: Pubkey,
+    pub breed_data: Pubkey,
+    pub owner: Pubkey,
+    pub parents: Vec<Pubkey>,
+    pub token_refund: u64,
+    pub sol_refund: u64,
+    pub in_progress: u64,
+    pub timestamp: i64,
+}
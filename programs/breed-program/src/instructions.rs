@@ -1,10 +1,28 @@
-use crate::{BreedConfig, BreedData, BreedMachine, BreedingError};
-use anchor_lang::prelude::*;
+use crate::{
+    randomness, BreedConfig, BreedData, BreedMachine, BreedingError, CancelPolicy, ChildNft,
+    FeeMode, LegacyBreedData, LegacyBreedMachine, Lineage, LockMode, ParentCollection,
+    ParentRecord, ParentValidation, MAX_BPS,
+};
+use anchor_lang::{
+    prelude::*,
+    solana_program::{program::invoke_signed, program_pack::Pack, sysvar},
+    system_program, Discriminator,
+};
 use anchor_spl::{
-    associated_token::AssociatedToken,
-    token::{Burn, CloseAccount, Mint, MintTo, SetAuthority, Token, TokenAccount, Transfer},
+    associated_token::{get_associated_token_address, AssociatedToken, Create},
+    token::{
+        spl_token, Approve, Burn, CloseAccount, InitializeMint, Mint, MintTo, Revoke, SetAuthority,
+        Token, TokenAccount, Transfer,
+    },
+};
+use mpl_token_metadata::{
+    instruction::{
+        create_master_edition_v3, create_metadata_accounts_v2, freeze_delegated_account,
+        thaw_delegated_account,
+    },
+    pda::{find_master_edition_account, find_metadata_account},
+    state::{Collection, Creator, Key, Metadata},
 };
-use solutils::wrappers::metadata::MetadataAccount;
 
 #[derive(Accounts)]
 #[instruction(config: BreedConfig)]
@@ -71,11 +89,238 @@ impl<'info> InitializeBreedMachine<'info> {
     }
 }
 
-#[derive(Debug, Clone, AnchorSerialize, AnchorDeserialize)]
+#[derive(Accounts)]
+pub struct ReplenishRewards<'info> {
+    #[account(mut, has_one = authority)]
+    pub breeding_machine: Account<'info, BreedMachine>,
+
+    #[account(
+        mut,
+        seeds = [b"whitelist_token", breeding_machine.key().as_ref()],
+        bump,
+    )]
+    pub whitelist_token: Account<'info, Mint>,
+
+    #[account(
+        mut,
+        associated_token::mint = whitelist_token,
+        associated_token::authority = breeding_machine,
+    )]
+    pub whitelist_vault: Account<'info, TokenAccount>,
+
+    pub authority: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+}
+
+impl<'info> ReplenishRewards<'info> {
+    pub fn mint_to_ctx(&self) -> CpiContext<'_, '_, '_, 'info, MintTo<'info>> {
+        let accounts = MintTo {
+            mint: self.whitelist_token.to_account_info(),
+            to: self.whitelist_vault.to_account_info(),
+            authority: self.breeding_machine.to_account_info(),
+        };
+
+        CpiContext::new(self.token_program.to_account_info(), accounts)
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(tier: u8)]
+pub struct AddRewardTier<'info> {
+    #[account(mut, has_one = authority)]
+    pub breeding_machine: Account<'info, BreedMachine>,
+
+    #[account(
+        init,
+        payer = authority,
+        mint::decimals = 0_u8,
+        mint::authority = breeding_machine,
+        seeds = [b"whitelist_token", breeding_machine.key().as_ref(), &[tier]],
+        bump,
+    )]
+    pub tier_token: Account<'info, Mint>,
+
+    #[account(
+        init,
+        payer = authority,
+        associated_token::mint = tier_token,
+        associated_token::authority = breeding_machine,
+    )]
+    pub tier_vault: Account<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub authority: Signer<'info>,
+
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+}
+
+impl<'info> AddRewardTier<'info> {
+    pub fn mint_to_ctx(&self) -> CpiContext<'_, '_, '_, 'info, MintTo<'info>> {
+        let accounts = MintTo {
+            mint: self.tier_token.to_account_info(),
+            to: self.tier_vault.to_account_info(),
+            authority: self.breeding_machine.to_account_info(),
+        };
+
+        CpiContext::new(self.token_program.to_account_info(), accounts)
+    }
+
+    pub fn set_authority_ctx(&self) -> CpiContext<'_, '_, '_, 'info, SetAuthority<'info>> {
+        let accounts = SetAuthority {
+            account_or_mint: self.tier_token.to_account_info(),
+            current_authority: self.breeding_machine.to_account_info(),
+        };
+
+        CpiContext::new(self.token_program.to_account_info(), accounts)
+    }
+}
+
+/// Accounts of a reward tier besides the config one, passed in the remaining
+/// accounts in tier order. Each instruction only takes the leading accounts it
+/// uses.
+pub struct RewardTierAccounts<'a, 'info> {
+    pub mint: &'a AccountInfo<'info>,
+    // Machine ATA holding the tier whitelist tokens.
+    pub vault: &'a AccountInfo<'info>,
+    pub user_ata: Option<&'a AccountInfo<'info>>,
+}
+
+impl<'a, 'info> RewardTierAccounts<'a, 'info> {
+    // Mint and vault.
+    pub const INIT_LEN: usize = 2;
+    // Mint, vault and user ATA.
+    pub const FINALIZE_LEN: usize = 3;
+    // Mint and vault.
+    pub const CLOSE_LEN: usize = 2;
+
+    /// Splits the remaining accounts by tier, checking the mints and vaults.
+    pub fn load(
+        remaining_accounts: &'a [AccountInfo<'info>],
+        accounts_per_tier: usize,
+        machine: &Account<'info, BreedMachine>,
+    ) -> Result<Vec<Self>> {
+        require_eq!(
+            remaining_accounts.len(),
+            machine.extra_reward_tiers.len() * accounts_per_tier,
+            BreedingError::InvalidRewardTier
+        );
+
+        remaining_accounts
+            .chunks_exact(accounts_per_tier)
+            .zip(&machine.extra_reward_tiers)
+            .enumerate()
+            .map(|(i, (accounts, reward_tier))| {
+                let tier = i as u8 + 1;
+                let mint = Pubkey::create_program_address(
+                    &[
+                        b"whitelist_token",
+                        machine.key().as_ref(),
+                        &[tier],
+                        &[reward_tier.bump],
+                    ],
+                    &crate::ID,
+                )
+                .map_err(|_| ErrorCode::ConstraintSeeds)?;
+
+                require_keys_eq!(accounts[0].key(), mint, ErrorCode::ConstraintSeeds);
+                require_keys_eq!(
+                    accounts[1].key(),
+                    get_associated_token_address(&machine.key(), &mint),
+                    ErrorCode::ConstraintAssociated
+                );
+
+                Ok(Self {
+                    mint: &accounts[0],
+                    vault: &accounts[1],
+                    user_ata: accounts.get(2),
+                })
+            })
+            .collect()
+    }
+
+    /// Whitelist tokens left in the vault.
+    pub fn amount(&self) -> Result<u64> {
+        Ok(Account::<TokenAccount>::try_from(self.vault)?.amount)
+    }
+
+    /// Whitelist tokens left in the vaults of these tiers and the config one.
+    pub fn rewards_left(whitelist_vault: &TokenAccount, tiers: &[Self]) -> Result<u64> {
+        tiers.iter().try_fold(whitelist_vault.amount, |left, tier| {
+            left.checked_add(tier.amount()?)
+                .ok_or_else(|| BreedingError::ArithmeticError.into())
+        })
+    }
+
+    /// Whitelist tokens left in the config vault, then in each tier vault.
+    pub fn amounts(whitelist_vault: &TokenAccount, tiers: &[Self]) -> Result<Vec<u64>> {
+        let mut amounts = vec![whitelist_vault.amount];
+        for tier in tiers {
+            amounts.push(tier.amount()?);
+        }
+
+        Ok(amounts)
+    }
+
+    /// Weights of the tiers with these `amounts` left. The weight of empty
+    /// tiers goes to the others.
+    pub fn weights(machine: &BreedMachine, amounts: &[u64]) -> Vec<u64> {
+        machine
+            .reward_weights()
+            .zip(amounts)
+            .map(|(weight, amount)| if *amount > 0 { weight as u64 } else { 0 })
+            .collect()
+    }
+
+    /// Burns the tokens left in the vault and closes it.
+    pub fn burn_and_close(
+        &self,
+        machine: &AccountInfo<'info>,
+        destination: &AccountInfo<'info>,
+        token_program: &AccountInfo<'info>,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        let leftover = self.amount()?;
+        if leftover > 0 {
+            let accounts = Burn {
+                from: self.vault.clone(),
+                mint: self.mint.clone(),
+                authority: machine.clone(),
+            };
+            let cpi = CpiContext::new(token_program.clone(), accounts);
+            anchor_spl::token::burn(cpi.with_signer(signer_seeds), leftover)?;
+        }
+
+        let accounts = CloseAccount {
+            account: self.vault.clone(),
+            destination: destination.clone(),
+            authority: machine.clone(),
+        };
+        let cpi = CpiContext::new(token_program.clone(), accounts);
+        anchor_spl::token::close_account(cpi.with_signer(signer_seeds))
+    }
+}
+
+#[derive(Debug, Clone, Default, AnchorSerialize, AnchorDeserialize)]
 pub struct UpdateMachineConfigArgs {
     pub breeding_time: Option<u64>,
     pub burn_parents: Option<bool>,
+    pub parents_collection: Option<Pubkey>,
+    pub parent_validation: Option<ParentValidation>,
+    pub parents_count: Option<u8>,
     pub initialization_fee_price: Option<u64>,
+    pub breeding_cooldown: Option<u64>,
+    pub max_breeds_per_parent: Option<u64>,
+    pub fee_mode: Option<FeeMode>,
+    pub fee_treasury: Option<Pubkey>,
+    pub sol_fee_lamports: Option<u64>,
+    pub sol_fee_treasury: Option<Pubkey>,
+    pub cancel_policy: Option<CancelPolicy>,
+    pub lock_mode: Option<LockMode>,
+    pub success_bps: Option<u16>,
 }
 
 #[derive(Accounts)]
@@ -87,39 +332,142 @@ pub struct UpdateMachineConfig<'info> {
 }
 
 #[derive(Accounts)]
-pub struct InitializeBreed<'info> {
+pub struct ToggleMachine<'info> {
+    #[account(mut, has_one = authority)]
+    pub breeding_machine: Account<'info, BreedMachine>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ProposeAuthority<'info> {
+    #[account(mut, has_one = authority)]
+    pub breeding_machine: Account<'info, BreedMachine>,
+
+    pub authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAuthority<'info> {
     #[account(
         mut,
-        seeds = [
-            BreedMachine::PREFIX,
-            breeding_machine.config.parents_candy_machine.as_ref(),
-            breeding_machine.config.reward_candy_machine.as_ref(),
-            breeding_machine.authority.key().as_ref(),
-        ],
-        bump
+        constraint = breeding_machine.pending_authority == Some(new_authority.key())
+            @ BreedingError::InvalidPendingAuthority,
     )]
     pub breeding_machine: Account<'info, BreedMachine>,
 
-    #[account(
-        init,
-        payer = user_wallet,
-        space = 8 + BreedData::LEN,
-        seeds = [
-            BreedData::PREFIX,
-            breeding_machine.key().as_ref(),
-            mint_parent_a.key().as_ref(),
-            mint_parent_b.key().as_ref(),
-        ],
-        bump
-    )]
-    pub breed_data: Account<'info, BreedData>,
+    pub new_authority: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateMachine<'info> {
+    /// CHECK: Still in the legacy layout, validated in `MigrateMachine::migrate`.
+    #[account(mut, owner = crate::ID)]
+    pub breeding_machine: UncheckedAccount<'info>,
 
-    pub mint_parent_a: Account<'info, Mint>,
+    #[account(mut)]
+    pub authority: Signer<'info>,
 
-    #[account(
-        constraint = metadata_parent_a.mint == mint_parent_a.key(),
-    )]
-    pub metadata_parent_a: Box<Account<'info, MetadataAccount>>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> MigrateMachine<'info> {
+    /// Rewrites the machine in the current layout and returns it.
+    pub fn migrate(&self) -> Result<BreedMachine> {
+        let info = self.breeding_machine.to_account_info();
+
+        let legacy = {
+            let data = info.try_borrow_data()?;
+            require_eq!(
+                data.len(),
+                8 + LegacyBreedMachine::LEN,
+                BreedingError::AlreadyMigrated
+            );
+            if data[..8] != BreedMachine::discriminator() {
+                return err!(ErrorCode::AccountDiscriminatorMismatch);
+            }
+            LegacyBreedMachine::deserialize(&mut &data[8..])?
+        };
+
+        require_keys_eq!(
+            legacy.authority,
+            self.authority.key(),
+            ErrorCode::ConstraintHasOne
+        );
+
+        // Legacy machines were derived from the authority, which becomes the creator.
+        let (address, bump) = Pubkey::find_program_address(
+            &[
+                BreedMachine::PREFIX,
+                legacy.config.parents_candy_machine.as_ref(),
+                legacy.config.reward_candy_machine.as_ref(),
+                legacy.authority.as_ref(),
+            ],
+            &crate::ID,
+        );
+        require_keys_eq!(address, info.key(), ErrorCode::ConstraintSeeds);
+
+        // Pay the rent for the extra space.
+        let space = 8 + BreedMachine::LEN;
+        let rent = Rent::get()?
+            .minimum_balance(space)
+            .saturating_sub(info.lamports());
+
+        if rent > 0 {
+            let accounts = system_program::Transfer {
+                from: self.authority.to_account_info(),
+                to: info.clone(),
+            };
+            let cpi = CpiContext::new(self.system_program.to_account_info(), accounts);
+            system_program::transfer(cpi, rent)?;
+        }
+
+        info.realloc(space, false)?;
+
+        let machine = BreedMachine {
+            authority: legacy.authority,
+            pending_authority: None,
+            creator: legacy.authority,
+            bump,
+            bred: legacy.bred,
+            born: legacy.born,
+            // Legacy breedings keep their own layout and are settled by
+            // `finalize_legacy_breeding` or `cancel_legacy_breeding`, which
+            // don't use these counters. No breeding in the current layout
+            // exists yet, so none is in progress and no whitelist token is
+            // reserved.
+            reserved: 0,
+            in_progress: 0,
+            paused: false,
+            extra_parent_collections: vec![],
+            compatibility: None,
+            child_nft: None,
+            config: legacy.config.into(),
+            base_reward_weight: 1,
+            extra_reward_tiers: vec![],
+        };
+
+        let mut data = info.try_borrow_mut_data()?;
+        let mut writer: &mut [u8] = &mut data;
+        machine.try_serialize(&mut writer)?;
+
+        Ok(machine)
+    }
+}
+
+#[derive(Accounts)]
+pub struct CancelLegacyBreeding<'info> {
+    /// CHECK: Only used in the legacy breed data seeds. The machine may have
+    /// been migrated or closed since the breeding started.
+    pub breeding_machine: UncheckedAccount<'info>,
+
+    /// CHECK: Still in the legacy layout, validated in `LegacyLock::load`.
+    #[account(mut, owner = crate::ID)]
+    pub breed_data: UncheckedAccount<'info>,
+
+    pub mint_parent_a: Box<Account<'info, Mint>>,
+
+    pub mint_parent_b: Box<Account<'info, Mint>>,
 
     #[account(
         mut,
@@ -128,21 +476,6 @@ pub struct InitializeBreed<'info> {
     )]
     pub user_ata_parent_a: Box<Account<'info, TokenAccount>>,
 
-    #[account(
-        init,
-        payer = user_wallet,
-        associated_token::mint = mint_parent_a,
-        associated_token::authority = breed_data
-    )]
-    pub vault_ata_parent_a: Box<Account<'info, TokenAccount>>,
-
-    pub mint_parent_b: Account<'info, Mint>,
-
-    #[account(
-        constraint = metadata_parent_b.mint == mint_parent_b.key(),
-    )]
-    pub metadata_parent_b: Box<Account<'info, MetadataAccount>>,
-
     #[account(
         mut,
         associated_token::mint = mint_parent_b,
@@ -151,129 +484,71 @@ pub struct InitializeBreed<'info> {
     pub user_ata_parent_b: Box<Account<'info, TokenAccount>>,
 
     #[account(
-        init,
-        payer = user_wallet,
-        associated_token::mint = mint_parent_b,
+        mut,
+        associated_token::mint = mint_parent_a,
         associated_token::authority = breed_data
     )]
-    pub vault_ata_parent_b: Box<Account<'info, TokenAccount>>,
-
-    #[account(mut, address = breeding_machine.config.initialization_fee_token)]
-    pub fee_token: Account<'info, Mint>,
+    pub vault_ata_parent_a: Box<Account<'info, TokenAccount>>,
 
     #[account(
         mut,
-        associated_token::mint = fee_token,
-        associated_token::authority = user_wallet
+        associated_token::mint = mint_parent_b,
+        associated_token::authority = breed_data
     )]
-    pub fee_payer_ata: Box<Account<'info, TokenAccount>>,
+    pub vault_ata_parent_b: Box<Account<'info, TokenAccount>>,
 
     #[account(mut)]
     pub user_wallet: Signer<'info>,
-    pub rent: Sysvar<'info, Rent>,
-    pub token_program: Program<'info, Token>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
-    pub system_program: Program<'info, System>,
-}
 
-fn verify_creator(metadata: &MetadataAccount, creator_address: Pubkey) -> Result<()> {
-    metadata
-        .data
-        .creators
-        .as_ref()
-        .filter(|c| c.iter().any(|c| c.address == creator_address && c.verified))
-        .ok_or(BreedingError::InvalidNftCollection)?;
-    Ok(())
+    pub token_program: Program<'info, Token>,
 }
 
-impl<'info> InitializeBreed<'info> {
-    pub fn validate_nfts(ctx: &Context<Self>) -> Result<()> {
-        verify_creator(
-            &*ctx.accounts.metadata_parent_a,
-            ctx.accounts.breeding_machine.config.parents_candy_machine,
-        )?;
-
-        verify_creator(
-            &*ctx.accounts.metadata_parent_b,
-            ctx.accounts.breeding_machine.config.parents_candy_machine,
-        )?;
-
-        Ok(())
-    }
-
-    pub fn charge_token_fee(ctx: &Context<Self>, amount: u64) -> Result<()> {
-        let fee_payer_ata = &*ctx.accounts.fee_payer_ata;
-        let fee_payer_ata_balance = fee_payer_ata.amount;
-        if fee_payer_ata_balance < amount {
-            return Err(ProgramError::InsufficientFunds.into());
+impl<'info> CancelLegacyBreeding<'info> {
+    pub fn legacy_lock(&self) -> LegacyLock<'info> {
+        LegacyLock {
+            breeding_machine: self.breeding_machine.to_account_info(),
+            breed_data: self.breed_data.to_account_info(),
+            mints: [
+                self.mint_parent_a.to_account_info(),
+                self.mint_parent_b.to_account_info(),
+            ],
+            user_atas: [
+                self.user_ata_parent_a.to_account_info(),
+                self.user_ata_parent_b.to_account_info(),
+            ],
+            vaults: [
+                self.vault_ata_parent_a.to_account_info(),
+                self.vault_ata_parent_b.to_account_info(),
+            ],
+            user_wallet: self.user_wallet.to_account_info(),
+            token_program: self.token_program.to_account_info(),
         }
-
-        let cpi_accounts = Burn {
-            from: ctx.accounts.fee_payer_ata.to_account_info(),
-            mint: ctx.accounts.fee_token.to_account_info(),
-            authority: ctx.accounts.user_wallet.to_account_info(),
-        };
-
-        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
-
-        anchor_spl::token::burn(cpi_ctx, amount)?;
-
-        Ok(())
-    }
-
-    pub fn lock_parents(&self) -> Result<()> {
-        // Lock NFT #1
-        let accounts = Transfer {
-            from: self.user_ata_parent_a.to_account_info(),
-            to: self.vault_ata_parent_a.to_account_info(),
-            authority: self.user_wallet.to_account_info(),
-        };
-        let cpi = CpiContext::new(self.token_program.to_account_info(), accounts);
-        anchor_spl::token::transfer(cpi, 1)?;
-
-        // Lock NFT #2
-        let accounts = Transfer {
-            from: self.user_ata_parent_b.to_account_info(),
-            to: self.vault_ata_parent_b.to_account_info(),
-            authority: self.user_wallet.to_account_info(),
-        };
-        let cpi = CpiContext::new(self.token_program.to_account_info(), accounts);
-        anchor_spl::token::transfer(cpi, 1)
     }
 }
 
 #[derive(Accounts)]
-pub struct FinalizeBreeding<'info> {
+pub struct FinalizeLegacyBreeding<'info> {
     #[account(
         mut,
         seeds = [
             BreedMachine::PREFIX,
             breeding_machine.config.parents_candy_machine.as_ref(),
             breeding_machine.config.reward_candy_machine.as_ref(),
-            breeding_machine.authority.key().as_ref(),
+            breeding_machine.creator.as_ref(),
         ],
-        bump
+        bump = breeding_machine.bump
     )]
     pub breeding_machine: Account<'info, BreedMachine>,
 
-    #[account(
-        mut,
-        close = user_wallet,
-        seeds = [
-            BreedData::PREFIX,
-            breeding_machine.key().as_ref(),
-            mint_parent_a.key().as_ref(),
-            mint_parent_b.key().as_ref(),
-        ],
-        bump
-    )]
-    pub breed_data: Account<'info, BreedData>,
+    /// CHECK: Still in the legacy layout, validated in `LegacyLock::load`.
+    #[account(mut, owner = crate::ID)]
+    pub breed_data: UncheckedAccount<'info>,
 
-    #[account(mut, address = breed_data.mint_a)]
-    pub mint_parent_a: Account<'info, Mint>,
+    #[account(mut)]
+    pub mint_parent_a: Box<Account<'info, Mint>>,
 
-    #[account(mut, address = breed_data.mint_b)]
-    pub mint_parent_b: Account<'info, Mint>,
+    #[account(mut)]
+    pub mint_parent_b: Box<Account<'info, Mint>>,
 
     #[account(
         mut,
@@ -281,6 +556,7 @@ pub struct FinalizeBreeding<'info> {
         associated_token::authority = user_wallet
     )]
     pub user_ata_parent_a: Box<Account<'info, TokenAccount>>,
+
     #[account(
         mut,
         associated_token::mint = mint_parent_b,
@@ -294,6 +570,7 @@ pub struct FinalizeBreeding<'info> {
         associated_token::authority = breed_data
     )]
     pub vault_ata_parent_a: Box<Account<'info, TokenAccount>>,
+
     #[account(
         mut,
         associated_token::mint = mint_parent_b,
@@ -324,79 +601,1325 @@ pub struct FinalizeBreeding<'info> {
     #[account(mut)]
     pub user_wallet: Signer<'info>,
 
-    pub rent: Sysvar<'info, Rent>,
     pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-    pub associated_token_program: Program<'info, AssociatedToken>,
 }
 
-impl<'info> FinalizeBreeding<'info> {
-    fn transfer_back(&self, signer_seeds: &[&[&[u8]]]) -> Result<()> {
-        let accounts = Transfer {
-            from: self.vault_ata_parent_a.to_account_info(),
-            to: self.user_ata_parent_a.to_account_info(),
-            authority: self.breed_data.to_account_info(),
-        };
-        let cpi = CpiContext::new(self.token_program.to_account_info(), accounts);
-        anchor_spl::token::transfer(cpi.with_signer(signer_seeds), 1)?;
+impl<'info> FinalizeLegacyBreeding<'info> {
+    pub fn legacy_lock(&self) -> LegacyLock<'info> {
+        LegacyLock {
+            breeding_machine: self.breeding_machine.to_account_info(),
+            breed_data: self.breed_data.to_account_info(),
+            mints: [
+                self.mint_parent_a.to_account_info(),
+                self.mint_parent_b.to_account_info(),
+            ],
+            user_atas: [
+                self.user_ata_parent_a.to_account_info(),
+                self.user_ata_parent_b.to_account_info(),
+            ],
+            vaults: [
+                self.vault_ata_parent_a.to_account_info(),
+                self.vault_ata_parent_b.to_account_info(),
+            ],
+            user_wallet: self.user_wallet.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+        }
+    }
 
+    pub fn transfer_ctx(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
         let accounts = Transfer {
-            from: self.vault_ata_parent_b.to_account_info(),
-            to: self.user_ata_parent_b.to_account_info(),
-            authority: self.breed_data.to_account_info(),
+            from: self.whitelist_vault.to_account_info(),
+            to: self.user_whitelist_ata.to_account_info(),
+            authority: self.breeding_machine.to_account_info(),
         };
-        let cpi = CpiContext::new(self.token_program.to_account_info(), accounts);
-        anchor_spl::token::transfer(cpi.with_signer(signer_seeds), 1)?;
+
+        CpiContext::new(self.token_program.to_account_info(), accounts)
+    }
+}
+
+/// Accounts of a breeding started before its machine was migrated, with both
+/// parents in vault ATAs of the legacy breed data.
+pub struct LegacyLock<'info> {
+    // Only used in the legacy breed data seeds.
+    pub breeding_machine: AccountInfo<'info>,
+    pub breed_data: AccountInfo<'info>,
+    pub mints: [AccountInfo<'info>; 2],
+    pub user_atas: [AccountInfo<'info>; 2],
+    pub vaults: [AccountInfo<'info>; 2],
+    pub user_wallet: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+}
+
+impl<'info> LegacyLock<'info> {
+    /// Reads the legacy breed data, checking its owner, mints and address.
+    /// Returns it with its bump.
+    pub fn load(&self) -> Result<(LegacyBreedData, u8)> {
+        let legacy = {
+            let data = self.breed_data.try_borrow_data()?;
+            require_eq!(
+                data.len(),
+                8 + LegacyBreedData::LEN,
+                ErrorCode::AccountDidNotDeserialize
+            );
+            if data[..8] != BreedData::discriminator() {
+                return err!(ErrorCode::AccountDiscriminatorMismatch);
+            }
+            LegacyBreedData::deserialize(&mut &data[8..])?
+        };
+
+        require_keys_eq!(
+            legacy.owner,
+            self.user_wallet.key(),
+            BreedingError::InvalidBreedOwner
+        );
+        require_keys_eq!(
+            legacy.mint_a,
+            self.mints[0].key(),
+            ErrorCode::ConstraintAddress
+        );
+        require_keys_eq!(
+            legacy.mint_b,
+            self.mints[1].key(),
+            ErrorCode::ConstraintAddress
+        );
+
+        let (address, bump) = Pubkey::find_program_address(
+            &[
+                BreedData::PREFIX,
+                self.breeding_machine.key.as_ref(),
+                legacy.mint_a.as_ref(),
+                legacy.mint_b.as_ref(),
+            ],
+            &crate::ID,
+        );
+        require_keys_eq!(address, self.breed_data.key(), ErrorCode::ConstraintSeeds);
+
+        Ok((legacy, bump))
+    }
+
+    /// Burns or returns both parents to the owner, then closes their vaults
+    /// and the legacy breed data.
+    pub fn release(&self, legacy: &LegacyBreedData, bump: u8, burn: bool) -> Result<()> {
+        let breed_data_seeds: &[&[u8]] = &[
+            BreedData::PREFIX,
+            self.breeding_machine.key.as_ref(),
+            legacy.mint_a.as_ref(),
+            legacy.mint_b.as_ref(),
+            &[bump], // must come last
+        ];
+
+        let parents = self.mints.iter().zip(&self.vaults).zip(&self.user_atas);
+        for ((mint, vault), user_ata) in parents {
+            if burn {
+                let accounts = Burn {
+                    from: vault.clone(),
+                    mint: mint.clone(),
+                    authority: self.breed_data.clone(),
+                };
+                let cpi = CpiContext::new(self.token_program.clone(), accounts);
+                anchor_spl::token::burn(cpi.with_signer(&[breed_data_seeds]), 1)?;
+            } else {
+                let accounts = Transfer {
+                    from: vault.clone(),
+                    to: user_ata.clone(),
+                    authority: self.breed_data.clone(),
+                };
+                let cpi = CpiContext::new(self.token_program.clone(), accounts);
+                anchor_spl::token::transfer(cpi.with_signer(&[breed_data_seeds]), 1)?;
+            }
+
+            let accounts = CloseAccount {
+                account: vault.clone(),
+                destination: self.user_wallet.clone(),
+                authority: self.breed_data.clone(),
+            };
+            let cpi = CpiContext::new(self.token_program.clone(), accounts);
+            anchor_spl::token::close_account(cpi.with_signer(&[breed_data_seeds]))?;
+        }
+
+        // Close the breed data, the runtime drops it once it holds no lamports.
+        **self.user_wallet.try_borrow_mut_lamports()? = self
+            .user_wallet
+            .lamports()
+            .checked_add(self.breed_data.lamports())
+            .ok_or(BreedingError::ArithmeticError)?;
+        **self.breed_data.try_borrow_mut_lamports()? = 0;
+        self.breed_data.try_borrow_mut_data()?.fill(0);
 
         Ok(())
     }
+}
 
-    fn burn(&self, signer_seeds: &[&[&[u8]]]) -> Result<()> {
-        let accounts = Burn {
-            from: self.vault_ata_parent_a.to_account_info(),
-            mint: self.mint_parent_a.to_account_info(),
-            authority: self.breed_data.to_account_info(),
+/// Parent accounts are passed in the remaining accounts, see `ParentAccounts`.
+#[derive(Accounts)]
+#[instruction(parents: Vec<Pubkey>)]
+pub struct InitializeBreed<'info> {
+    #[account(
+        mut,
+        constraint = !breeding_machine.paused @ BreedingError::MachinePaused,
+        seeds = [
+            BreedMachine::PREFIX,
+            breeding_machine.config.parents_candy_machine.as_ref(),
+            breeding_machine.config.reward_candy_machine.as_ref(),
+            breeding_machine.creator.as_ref(),
+        ],
+        bump = breeding_machine.bump
+    )]
+    pub breeding_machine: Account<'info, BreedMachine>,
+
+    // Parents are sorted by mint so each set of parents has a single breed
+    // data address. Parents are locked while breeding, so the first one is
+    // enough to tell breedings apart.
+    #[account(
+        init,
+        payer = user_wallet,
+        space = 8 + BreedData::LEN,
+        constraint = parents.len() == breeding_machine.config.parents_count as usize
+            @ BreedingError::InvalidParentCount,
+        constraint = parents.windows(2).all(|p| p[0] != p[1]) @ BreedingError::SelfBreeding,
+        constraint = parents.windows(2).all(|p| p[0] < p[1]) @ BreedingError::UnsortedParents,
+        seeds = [
+            BreedData::PREFIX,
+            breeding_machine.key().as_ref(),
+            BreedData::seed_mint(&parents).as_ref(),
+        ],
+        bump
+    )]
+    pub breed_data: Account<'info, BreedData>,
+
+    /// CHECK: Checked in `charge_token_fee` when the machine charges a token
+    /// fee. Pass the user wallet when it doesn't.
+    #[account(mut)]
+    pub fee_token: UncheckedAccount<'info>,
+
+    /// CHECK: Checked in `charge_token_fee` when the machine charges a token
+    /// fee. Pass the user wallet when it doesn't.
+    #[account(mut)]
+    pub fee_payer_ata: UncheckedAccount<'info>,
+
+    /// CHECK: Breed data ATA holding the token fee until the breeding is over,
+    /// created in `charge_token_fee`. Pass the user wallet when the machine
+    /// doesn't charge a token fee.
+    #[account(mut)]
+    pub fee_vault: UncheckedAccount<'info>,
+
+    #[account(
+        seeds = [b"whitelist_token", breeding_machine.key().as_ref()],
+        bump,
+    )]
+    pub whitelist_token: Box<Account<'info, Mint>>,
+
+    // Read to reserve a whitelist token for the breeding.
+    #[account(
+        associated_token::mint = whitelist_token,
+        associated_token::authority = breeding_machine
+    )]
+    pub whitelist_vault: Box<Account<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub user_wallet: Signer<'info>,
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Program<'info, Token>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+    pub system_program: Program<'info, System>,
+
+    /// CHECK: Token metadata program, freezing the parents in freeze mode.
+    #[account(address = mpl_token_metadata::ID)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+}
+
+fn verify_creator(metadata: &Metadata, creator_address: Pubkey) -> Result<()> {
+    metadata
+        .data
+        .creators
+        .as_ref()
+        .filter(|c| c.iter().any(|c| c.address == creator_address && c.verified))
+        .ok_or(BreedingError::InvalidNftCollection)?;
+    Ok(())
+}
+
+fn verify_collection(metadata: &Metadata, collection_address: Pubkey) -> Result<()> {
+    metadata
+        .collection
+        .as_ref()
+        .filter(|c| c.key == collection_address && c.verified)
+        .ok_or(BreedingError::InvalidNftCollection)?;
+    Ok(())
+}
+
+fn verify_parent(
+    metadata: &Metadata,
+    collection: &ParentCollection,
+    validation: ParentValidation,
+) -> Result<()> {
+    match validation {
+        ParentValidation::VerifiedCreator => verify_creator(metadata, collection.creator),
+        ParentValidation::VerifiedCollection => verify_collection(metadata, collection.collection),
+        ParentValidation::Both => {
+            verify_creator(metadata, collection.creator)?;
+            verify_collection(metadata, collection.collection)
+        }
+    }
+}
+
+/// Bitmask of the machine parent collections the NFT belongs to.
+fn parent_collections_mask(metadata: &Metadata, machine: &BreedMachine) -> Result<u8> {
+    let validation = machine.config.parent_validation;
+    let mask = machine
+        .parent_collections()
+        .enumerate()
+        .filter(|(_, collection)| verify_parent(metadata, collection, validation).is_ok())
+        .fold(0, |mask, (i, _)| mask | 1 << i);
+
+    require_neq!(mask, 0, BreedingError::InvalidNftCollection);
+
+    Ok(mask)
+}
+
+/// Accounts of a parent, passed in the remaining accounts in the order of the
+/// parent mints. Each instruction only takes the leading accounts it uses.
+pub struct ParentAccounts<'a, 'info> {
+    pub mint: &'a AccountInfo<'info>,
+    pub user_ata: &'a AccountInfo<'info>,
+    // Breed data ATA in vault mode, master edition in freeze mode.
+    pub lock_account: &'a AccountInfo<'info>,
+    pub parent_record: Option<&'a AccountInfo<'info>>,
+    pub metadata: Option<&'a AccountInfo<'info>>,
+}
+
+impl<'a, 'info> ParentAccounts<'a, 'info> {
+    // Mint, user ATA, lock account, parent record and metadata.
+    pub const INIT_LEN: usize = 5;
+    // Mint, user ATA, lock account and parent record.
+    pub const FINALIZE_LEN: usize = 4;
+    // Mint, user ATA and lock account.
+    pub const CANCEL_LEN: usize = 3;
+
+    /// Splits the remaining accounts between the parents and the reward tier
+    /// accounts following them.
+    pub fn split(
+        remaining_accounts: &'a [AccountInfo<'info>],
+        accounts_per_parent: usize,
+        mints: &[Pubkey],
+    ) -> (&'a [AccountInfo<'info>], &'a [AccountInfo<'info>]) {
+        let parents_len = (mints.len() * accounts_per_parent).min(remaining_accounts.len());
+        remaining_accounts.split_at(parents_len)
+    }
+
+    /// Splits the remaining accounts by parent.
+    pub fn load(
+        remaining_accounts: &'a [AccountInfo<'info>],
+        accounts_per_parent: usize,
+        mints: &[Pubkey],
+    ) -> Result<Vec<Self>> {
+        require_eq!(
+            remaining_accounts.len(),
+            mints.len() * accounts_per_parent,
+            BreedingError::InvalidParentCount
+        );
+
+        remaining_accounts
+            .chunks_exact(accounts_per_parent)
+            .zip(mints)
+            .map(|(accounts, mint)| {
+                require_keys_eq!(accounts[0].key(), *mint, ErrorCode::ConstraintAddress);
+
+                Ok(Self {
+                    mint: &accounts[0],
+                    user_ata: &accounts[1],
+                    lock_account: &accounts[2],
+                    parent_record: accounts.get(3),
+                    metadata: accounts.get(4),
+                })
+            })
+            .collect()
+    }
+
+    pub fn check_lock_accounts(
+        &self,
+        user_wallet: &Pubkey,
+        breed_data: &Pubkey,
+        lock_mode: LockMode,
+    ) -> Result<()> {
+        require_keys_eq!(
+            self.user_ata.key(),
+            get_associated_token_address(user_wallet, self.mint.key),
+            ErrorCode::ConstraintAssociated
+        );
+
+        match lock_mode {
+            LockMode::Vault => require_keys_eq!(
+                self.lock_account.key(),
+                get_associated_token_address(breed_data, self.mint.key),
+                ErrorCode::ConstraintAssociated
+            ),
+            // The token metadata program checks the edition too, this only
+            // gives a clearer error.
+            LockMode::Freeze => require_keys_eq!(
+                self.lock_account.key(),
+                find_master_edition_account(self.mint.key).0,
+                ErrorCode::ConstraintSeeds
+            ),
+        }
+
+        Ok(())
+    }
+
+    /// Freezes the parent in the user ATA, `delegate` must be its delegate.
+    pub fn freeze(
+        &self,
+        delegate: &AccountInfo<'info>,
+        token_program: &AccountInfo<'info>,
+        token_metadata_program: &AccountInfo<'info>,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        let ix = freeze_delegated_account(
+            mpl_token_metadata::ID,
+            delegate.key(),
+            self.user_ata.key(),
+            self.lock_account.key(),
+            self.mint.key(),
+        );
+
+        invoke_signed(
+            &ix,
+            &[
+                delegate.clone(),
+                self.user_ata.clone(),
+                self.lock_account.clone(),
+                self.mint.clone(),
+                token_program.clone(),
+                token_metadata_program.clone(),
+            ],
+            signer_seeds,
+        )
+        .map_err(Into::into)
+    }
+
+    /// Thaws the parent frozen by `freeze`.
+    pub fn thaw(
+        &self,
+        delegate: &AccountInfo<'info>,
+        token_program: &AccountInfo<'info>,
+        token_metadata_program: &AccountInfo<'info>,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        let ix = thaw_delegated_account(
+            mpl_token_metadata::ID,
+            delegate.key(),
+            self.user_ata.key(),
+            self.lock_account.key(),
+            self.mint.key(),
+        );
+
+        invoke_signed(
+            &ix,
+            &[
+                delegate.clone(),
+                self.user_ata.clone(),
+                self.lock_account.clone(),
+                self.mint.clone(),
+                token_program.clone(),
+                token_metadata_program.clone(),
+            ],
+            signer_seeds,
+        )
+        .map_err(Into::into)
+    }
+
+    pub fn metadata(&self) -> Result<Metadata> {
+        let info = self.metadata.ok_or(ErrorCode::AccountNotEnoughKeys)?;
+        require_keys_eq!(
+            *info.owner,
+            mpl_token_metadata::ID,
+            ErrorCode::AccountOwnedByWrongProgram
+        );
+
+        // Metadata accounts are padded, so only their prefix is read.
+        let metadata = Metadata::deserialize(&mut &info.try_borrow_data()?[..])
+            .map_err(|_| ErrorCode::AccountDidNotDeserialize)?;
+        require!(
+            metadata.key == Key::MetadataV1,
+            ErrorCode::AccountDiscriminatorMismatch
+        );
+        require_keys_eq!(metadata.mint, self.mint.key(), ErrorCode::ConstraintRaw);
+
+        Ok(metadata)
+    }
+
+    /// Loads the parent record, checking its address.
+    pub fn parent_record(&self) -> Result<Account<'info, ParentRecord>> {
+        let info = self.parent_record.ok_or(ErrorCode::AccountNotEnoughKeys)?;
+        let record = Account::<ParentRecord>::try_from(info)?;
+
+        let address = Pubkey::create_program_address(
+            &[ParentRecord::PREFIX, self.mint.key.as_ref(), &[record.bump]],
+            &crate::ID,
+        )
+        .map_err(|_| ErrorCode::ConstraintSeeds)?;
+        require_keys_eq!(address, info.key(), ErrorCode::ConstraintSeeds);
+
+        Ok(record)
+    }
+
+    /// Creates the parent record on the parent first breeding.
+    pub fn init_parent_record(
+        &self,
+        payer: &AccountInfo<'info>,
+        system_program: &AccountInfo<'info>,
+    ) -> Result<()> {
+        let info = self.parent_record.ok_or(ErrorCode::AccountNotEnoughKeys)?;
+        if !info.data_is_empty() {
+            return Ok(());
+        }
+
+        create_parent_record(info, self.mint.key, 0, payer, system_program)
+    }
+}
+
+/// Creates the record of `mint`, when it first breeds or when it's born from
+/// a breeding.
+fn create_parent_record<'info>(
+    info: &AccountInfo<'info>,
+    mint: &Pubkey,
+    generation: u16,
+    payer: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+) -> Result<()> {
+    let (address, bump) =
+        Pubkey::find_program_address(&[ParentRecord::PREFIX, mint.as_ref()], &crate::ID);
+    require_keys_eq!(address, info.key(), ErrorCode::ConstraintSeeds);
+
+    create_pda_account(
+        payer,
+        info,
+        system_program,
+        8 + ParentRecord::LEN,
+        &crate::ID,
+        &[ParentRecord::PREFIX, mint.as_ref(), &[bump]],
+    )?;
+
+    let record = ParentRecord {
+        mint: *mint,
+        times_bred: 0,
+        last_bred_at: 0,
+        generation,
+        bump,
+    };
+
+    let mut data = info.try_borrow_mut_data()?;
+    let mut writer: &mut [u8] = &mut data;
+    record.try_serialize(&mut writer)
+}
+
+/// Creates a rent exempt account owned by `owner` at a PDA of this program, for
+/// the accounts that can't be initialized by anchor. Like anchor `init`, it still
+/// works when someone sent lamports to the address beforehand.
+fn create_pda_account<'info>(
+    payer: &AccountInfo<'info>,
+    account: &AccountInfo<'info>,
+    system_program: &AccountInfo<'info>,
+    space: usize,
+    owner: &Pubkey,
+    signer_seeds: &[&[u8]],
+) -> Result<()> {
+    let rent = Rent::get()?.minimum_balance(space);
+
+    if account.lamports() == 0 {
+        let accounts = system_program::CreateAccount {
+            from: payer.clone(),
+            to: account.clone(),
         };
-        let cpi = CpiContext::new(self.token_program.to_account_info(), accounts);
-        anchor_spl::token::burn(cpi.with_signer(signer_seeds), 1)?;
+        let cpi = CpiContext::new(system_program.clone(), accounts);
+        return system_program::create_account(
+            cpi.with_signer(&[signer_seeds]),
+            rent,
+            space as u64,
+            owner,
+        );
+    }
 
-        let accounts = Burn {
-            from: self.vault_ata_parent_b.to_account_info(),
-            mint: self.mint_parent_b.to_account_info(),
-            authority: self.breed_data.to_account_info(),
+    // `create_account` fails on funded addresses, top up the rent instead.
+    let top_up = rent.saturating_sub(account.lamports());
+    if top_up > 0 {
+        let accounts = system_program::Transfer {
+            from: payer.clone(),
+            to: account.clone(),
         };
-        let cpi = CpiContext::new(self.token_program.to_account_info(), accounts);
-        anchor_spl::token::burn(cpi.with_signer(signer_seeds), 1)?;
+        let cpi = CpiContext::new(system_program.clone(), accounts);
+        system_program::transfer(cpi, top_up)?;
+    }
+
+    let accounts = system_program::Allocate {
+        account_to_allocate: account.clone(),
+    };
+    let cpi = CpiContext::new(system_program.clone(), accounts);
+    system_program::allocate(cpi.with_signer(&[signer_seeds]), space as u64)?;
+
+    let accounts = system_program::Assign {
+        account_to_assign: account.clone(),
+    };
+    let cpi = CpiContext::new(system_program.clone(), accounts);
+    system_program::assign(cpi.with_signer(&[signer_seeds]), owner)
+}
+
+/// Accounts unlocking the parents of a breeding when it is finalized or
+/// cancelled.
+pub struct ParentLock<'info> {
+    pub breed_data: AccountInfo<'info>,
+    pub user_wallet: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+    pub token_metadata_program: AccountInfo<'info>,
+}
+
+impl<'info> ParentLock<'info> {
+    /// Burns or returns the locked parents with the lock mode the breeding
+    /// started with. Vaults are closed, frozen parents are thawed and their
+    /// delegation revoked.
+    pub fn release(
+        &self,
+        parents: &[ParentAccounts<'_, 'info>],
+        burn: bool,
+        lock_mode: LockMode,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        for parent in parents {
+            parent.check_lock_accounts(self.user_wallet.key, self.breed_data.key, lock_mode)?;
+
+            match lock_mode {
+                LockMode::Vault => self.release_from_vault(parent, burn, signer_seeds)?,
+                LockMode::Freeze => self.release_frozen(parent, burn, signer_seeds)?,
+            }
+        }
 
         Ok(())
     }
 
-    fn close_parent_vaults(&self, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+    fn release_from_vault(
+        &self,
+        parent: &ParentAccounts<'_, 'info>,
+        burn: bool,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        if burn {
+            let accounts = Burn {
+                from: parent.lock_account.clone(),
+                mint: parent.mint.clone(),
+                authority: self.breed_data.clone(),
+            };
+            let cpi = CpiContext::new(self.token_program.clone(), accounts);
+            anchor_spl::token::burn(cpi.with_signer(signer_seeds), 1)?;
+        } else {
+            let accounts = Transfer {
+                from: parent.lock_account.clone(),
+                to: parent.user_ata.clone(),
+                authority: self.breed_data.clone(),
+            };
+            let cpi = CpiContext::new(self.token_program.clone(), accounts);
+            anchor_spl::token::transfer(cpi.with_signer(signer_seeds), 1)?;
+        }
+
         let accounts = CloseAccount {
-            account: self.vault_ata_parent_a.to_account_info(),
-            destination: self.breed_data.to_account_info(),
+            account: parent.lock_account.clone(),
+            destination: self.breed_data.clone(),
+            authority: self.breed_data.clone(),
+        };
+        let cpi = CpiContext::new(self.token_program.clone(), accounts);
+        anchor_spl::token::close_account(cpi.with_signer(signer_seeds))
+    }
+
+    fn release_frozen(
+        &self,
+        parent: &ParentAccounts<'_, 'info>,
+        burn: bool,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        parent.thaw(
+            &self.breed_data,
+            &self.token_program,
+            &self.token_metadata_program,
+            signer_seeds,
+        )?;
+
+        if burn {
+            // Burning the whole delegated amount also clears the delegate.
+            let accounts = Burn {
+                from: parent.user_ata.clone(),
+                mint: parent.mint.clone(),
+                authority: self.breed_data.clone(),
+            };
+            let cpi = CpiContext::new(self.token_program.clone(), accounts);
+            anchor_spl::token::burn(cpi.with_signer(signer_seeds), 1)
+        } else {
+            let accounts = Revoke {
+                source: parent.user_ata.clone(),
+                authority: self.user_wallet.clone(),
+            };
+            let cpi = CpiContext::new(self.token_program.clone(), accounts);
+            anchor_spl::token::revoke(cpi)
+        }
+    }
+}
+
+impl<'info> InitializeBreed<'info> {
+    pub fn validate_nfts(ctx: &Context<'_, '_, '_, 'info, Self>, parents: &[Pubkey]) -> Result<()> {
+        let machine = &ctx.accounts.breeding_machine;
+
+        let (parent_infos, _) =
+            ParentAccounts::split(ctx.remaining_accounts, ParentAccounts::INIT_LEN, parents);
+        let masks = ParentAccounts::load(parent_infos, ParentAccounts::INIT_LEN, parents)?
+            .iter()
+            .map(|parent| parent_collections_mask(&parent.metadata()?, machine))
+            .collect::<Result<Vec<u8>>>()?;
+
+        // Every pair of parents must be able to breed together.
+        for (i, parent_a) in masks.iter().enumerate() {
+            for parent_b in &masks[i + 1..] {
+                require!(
+                    machine.can_breed_together(*parent_a, *parent_b),
+                    BreedingError::IncompatibleParents
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn validate_parent_records(
+        ctx: &Context<'_, '_, '_, 'info, Self>,
+        parents: &[Pubkey],
+    ) -> Result<()> {
+        let config = &ctx.accounts.breeding_machine.config;
+        let now = Clock::get()?.unix_timestamp;
+
+        let (parent_infos, _) =
+            ParentAccounts::split(ctx.remaining_accounts, ParentAccounts::INIT_LEN, parents);
+        for parent in ParentAccounts::load(parent_infos, ParentAccounts::INIT_LEN, parents)? {
+            let record = parent
+                .parent_record
+                .ok_or(ErrorCode::AccountNotEnoughKeys)?;
+
+            // Parents breeding for the first time don't have a record yet.
+            if !record.data_is_empty() {
+                parent.parent_record()?.can_breed(config, now)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    pub fn charge_token_fee(ctx: &Context<Self>, amount: u64) -> Result<()> {
+        if amount == 0 {
+            return Ok(());
+        }
+
+        let config = &ctx.accounts.breeding_machine.config;
+
+        require_keys_eq!(
+            ctx.accounts.fee_token.key(),
+            config.initialization_fee_token,
+            ErrorCode::ConstraintAddress
+        );
+
+        let fee_payer_ata = Account::<TokenAccount>::try_from(&ctx.accounts.fee_payer_ata)?;
+        require_keys_eq!(
+            fee_payer_ata.mint,
+            config.initialization_fee_token,
+            ErrorCode::ConstraintTokenMint
+        );
+        require_keys_eq!(
+            fee_payer_ata.owner,
+            ctx.accounts.user_wallet.key(),
+            ErrorCode::ConstraintTokenOwner
+        );
+
+        let fee_payer_ata_balance = fee_payer_ata.amount;
+        if fee_payer_ata_balance < amount {
+            return Err(ProgramError::InsufficientFunds.into());
+        }
+
+        // Escrow the fee until the breeding is finalized or cancelled.
+        require_keys_eq!(
+            ctx.accounts.fee_vault.key(),
+            get_associated_token_address(
+                &ctx.accounts.breed_data.key(),
+                &ctx.accounts.fee_token.key()
+            ),
+            ErrorCode::ConstraintAssociated
+        );
+
+        if ctx.accounts.fee_vault.data_is_empty() {
+            let cpi_accounts = Create {
+                payer: ctx.accounts.user_wallet.to_account_info(),
+                associated_token: ctx.accounts.fee_vault.to_account_info(),
+                authority: ctx.accounts.breed_data.to_account_info(),
+                mint: ctx.accounts.fee_token.to_account_info(),
+                system_program: ctx.accounts.system_program.to_account_info(),
+                token_program: ctx.accounts.token_program.to_account_info(),
+                rent: ctx.accounts.rent.to_account_info(),
+            };
+
+            let cpi_ctx = CpiContext::new(
+                ctx.accounts.associated_token_program.to_account_info(),
+                cpi_accounts,
+            );
+
+            anchor_spl::associated_token::create(cpi_ctx)?;
+        }
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.fee_payer_ata.to_account_info(),
+            to: ctx.accounts.fee_vault.to_account_info(),
+            authority: ctx.accounts.user_wallet.to_account_info(),
+        };
+
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+
+        anchor_spl::token::transfer(cpi_ctx, amount)
+    }
+
+    pub fn charge_sol_fee(ctx: &Context<Self>, lamports: u64) -> Result<()> {
+        if lamports == 0 {
+            return Ok(());
+        }
+
+        // Escrowed in the breed data account until the breeding is over.
+        let accounts = system_program::Transfer {
+            from: ctx.accounts.user_wallet.to_account_info(),
+            to: ctx.accounts.breed_data.to_account_info(),
+        };
+        let cpi = CpiContext::new(ctx.accounts.system_program.to_account_info(), accounts);
+        system_program::transfer(cpi, lamports)
+    }
+
+    pub fn init_parent_records(&self, parents: &[ParentAccounts<'_, 'info>]) -> Result<()> {
+        for parent in parents {
+            parent.init_parent_record(
+                &self.user_wallet.to_account_info(),
+                &self.system_program.to_account_info(),
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// Reserves a whitelist token when the machine sends them, failing once
+    /// every token left is reserved. Returns whether a token was reserved.
+    pub fn reserve_reward(&mut self, tiers: &[RewardTierAccounts<'_, 'info>]) -> Result<bool> {
+        // Child NFTs have no supply to run out of.
+        if self.breeding_machine.child_nft.is_some() {
+            return Ok(false);
+        }
+
+        let rewards_left = RewardTierAccounts::rewards_left(&self.whitelist_vault, tiers)?;
+        let machine = &mut self.breeding_machine;
+        require_gt!(
+            rewards_left,
+            machine.reserved,
+            BreedingError::RewardsSoldOut
+        );
+
+        machine.reserved = machine
+            .reserved
+            .checked_add(1)
+            .ok_or(BreedingError::ArithmeticError)?;
+
+        Ok(true)
+    }
+
+    /// Whether the outcome of a breeding starting now is rolled: it can fail,
+    /// or picks a whitelist token among several weighted tiers.
+    pub fn rolls_outcome(&self, tiers: &[RewardTierAccounts<'_, 'info>]) -> Result<bool> {
+        if self.breeding_machine.config.success_bps < MAX_BPS {
+            return Ok(true);
+        }
+
+        // Child NFTs have no tiers to pick from.
+        if self.breeding_machine.child_nft.is_some() {
+            return Ok(false);
+        }
+
+        let amounts = RewardTierAccounts::amounts(&self.whitelist_vault, tiers)?;
+        let weights = RewardTierAccounts::weights(&self.breeding_machine, &amounts);
+
+        Ok(weights.iter().filter(|weight| **weight > 0).count() > 1)
+    }
+
+    /// Locks the parents with the machine lock mode. `signer_seeds` are the
+    /// breed data seeds, freezing the parents as their delegate.
+    pub fn lock_parents(
+        &self,
+        parents: &[ParentAccounts<'_, 'info>],
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        let lock_mode = self.breed_data.lock_mode;
+
+        for parent in parents {
+            parent.check_lock_accounts(
+                &self.user_wallet.key(),
+                &self.breed_data.key(),
+                lock_mode,
+            )?;
+
+            match lock_mode {
+                LockMode::Vault => self.move_to_vault(parent)?,
+                LockMode::Freeze => self.freeze_in_place(parent, signer_seeds)?,
+            }
+        }
+
+        Ok(())
+    }
+
+    fn move_to_vault(&self, parent: &ParentAccounts<'_, 'info>) -> Result<()> {
+        let accounts = Create {
+            payer: self.user_wallet.to_account_info(),
+            associated_token: parent.lock_account.clone(),
             authority: self.breed_data.to_account_info(),
+            mint: parent.mint.clone(),
+            system_program: self.system_program.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+            rent: self.rent.to_account_info(),
+        };
+        let cpi = CpiContext::new(self.associated_token_program.to_account_info(), accounts);
+        anchor_spl::associated_token::create(cpi)?;
+
+        let accounts = Transfer {
+            from: parent.user_ata.clone(),
+            to: parent.lock_account.clone(),
+            authority: self.user_wallet.to_account_info(),
         };
         let cpi = CpiContext::new(self.token_program.to_account_info(), accounts);
-        anchor_spl::token::close_account(cpi.with_signer(signer_seeds))?;
+        anchor_spl::token::transfer(cpi, 1)
+    }
 
-        let accounts = CloseAccount {
-            account: self.vault_ata_parent_b.to_account_info(),
-            destination: self.breed_data.to_account_info(),
-            authority: self.breed_data.to_account_info(),
+    fn freeze_in_place(
+        &self,
+        parent: &ParentAccounts<'_, 'info>,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        let accounts = Approve {
+            to: parent.user_ata.clone(),
+            delegate: self.breed_data.to_account_info(),
+            authority: self.user_wallet.to_account_info(),
         };
         let cpi = CpiContext::new(self.token_program.to_account_info(), accounts);
-        anchor_spl::token::close_account(cpi.with_signer(signer_seeds))?;
+        anchor_spl::token::approve(cpi, 1)?;
+
+        parent.freeze(
+            &self.breed_data.to_account_info(),
+            &self.token_program.to_account_info(),
+            &self.token_metadata_program.to_account_info(),
+            signer_seeds,
+        )
+    }
+}
+
+/// Fee accounts of a breeding, settled when it is finalized or cancelled.
+pub struct FeeEscrow<'info> {
+    pub breed_data: AccountInfo<'info>,
+    pub fee_token: AccountInfo<'info>,
+    pub fee_vault: AccountInfo<'info>,
+    pub fee_payer_ata: AccountInfo<'info>,
+    pub fee_treasury: AccountInfo<'info>,
+    pub sol_fee_treasury: AccountInfo<'info>,
+    pub user_wallet: AccountInfo<'info>,
+    pub token_program: AccountInfo<'info>,
+}
+
+impl<'info> FeeEscrow<'info> {
+    /// Refunds part of the escrowed fees to the user and settles the rest with
+    /// the fee mode and treasuries the breeding started with, so config
+    /// updates can't make it fail. Must run after any other CPI
+    /// signed by the breed data, as it moves lamports out of it.
+    pub fn settle(
+        &self,
+        breed_data: &BreedData,
+        token_refund: u64,
+        sol_refund: u64,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        if breed_data.fee_paid > 0 {
+            self.settle_token_fee(breed_data, token_refund, signer_seeds)?;
+        }
+
+        // The SOL refund goes back to the user when the breed data is closed.
+        let sol_kept = breed_data
+            .sol_fee_paid
+            .checked_sub(sol_refund)
+            .ok_or(BreedingError::ArithmeticError)?;
+
+        if sol_kept > 0 {
+            require_keys_eq!(
+                self.sol_fee_treasury.key(),
+                breed_data.sol_fee_treasury,
+                BreedingError::InvalidFeeTreasury
+            );
+
+            let mut breed_data_lamports = self.breed_data.try_borrow_mut_lamports()?;
+            let mut treasury_lamports = self.sol_fee_treasury.try_borrow_mut_lamports()?;
+
+            **breed_data_lamports = breed_data_lamports
+                .checked_sub(sol_kept)
+                .ok_or(BreedingError::ArithmeticError)?;
+            **treasury_lamports = treasury_lamports
+                .checked_add(sol_kept)
+                .ok_or(BreedingError::ArithmeticError)?;
+        }
 
         Ok(())
     }
 
-    pub fn unlock_parents(&self, signer_seeds: &[&[&[u8]]]) -> Result<()> {
-        match self.breeding_machine.config.burn_parents {
-            true => self.burn(signer_seeds)?,
-            false => self.transfer_back(signer_seeds)?,
+    fn settle_token_fee(
+        &self,
+        breed_data: &BreedData,
+        refund: u64,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        require_keys_eq!(
+            self.fee_token.key(),
+            breed_data.fee_token,
+            ErrorCode::ConstraintAddress
+        );
+        require_keys_eq!(
+            self.fee_vault.key(),
+            get_associated_token_address(&self.breed_data.key(), &breed_data.fee_token),
+            ErrorCode::ConstraintAssociated
+        );
+
+        if refund > 0 {
+            let accounts = Transfer {
+                from: self.fee_vault.clone(),
+                to: self.fee_payer_ata.clone(),
+                authority: self.breed_data.clone(),
+            };
+            let cpi = CpiContext::new(self.token_program.clone(), accounts);
+            anchor_spl::token::transfer(cpi.with_signer(signer_seeds), refund)?;
+        }
+
+        let kept = breed_data
+            .fee_paid
+            .checked_sub(refund)
+            .ok_or(BreedingError::ArithmeticError)?;
+        let (burn_amount, treasury_amount) = breed_data.fee_mode.split(kept);
+
+        if burn_amount > 0 {
+            let accounts = Burn {
+                from: self.fee_vault.clone(),
+                mint: self.fee_token.clone(),
+                authority: self.breed_data.clone(),
+            };
+            let cpi = CpiContext::new(self.token_program.clone(), accounts);
+            anchor_spl::token::burn(cpi.with_signer(signer_seeds), burn_amount)?;
+        }
+
+        if treasury_amount > 0 {
+            require_keys_eq!(
+                self.fee_treasury.key(),
+                breed_data.fee_treasury,
+                BreedingError::InvalidFeeTreasury
+            );
+
+            let accounts = Transfer {
+                from: self.fee_vault.clone(),
+                to: self.fee_treasury.clone(),
+                authority: self.breed_data.clone(),
+            };
+            let cpi = CpiContext::new(self.token_program.clone(), accounts);
+            anchor_spl::token::transfer(cpi.with_signer(signer_seeds), treasury_amount)?;
+        }
+
+        let accounts = CloseAccount {
+            account: self.fee_vault.clone(),
+            destination: self.user_wallet.clone(),
+            authority: self.breed_data.clone(),
+        };
+        let cpi = CpiContext::new(self.token_program.clone(), accounts);
+        anchor_spl::token::close_account(cpi.with_signer(signer_seeds))
+    }
+}
+
+/// Children minted on finalize have their mint at a PDA of the machine and the
+/// machine `born` counter when they were minted.
+pub const CHILD_MINT_PREFIX: &[u8] = b"child_mint";
+
+#[derive(Accounts)]
+pub struct RevealBreeding<'info> {
+    #[account(mut)]
+    pub breed_data: Account<'info, BreedData>,
+
+    /// CHECK: Read in `randomness::slot_hash_at`.
+    #[account(address = sysvar::slot_hashes::ID)]
+    pub slot_hashes: UncheckedAccount<'info>,
+}
+
+/// Parent accounts are passed in the remaining accounts, see `ParentAccounts`.
+#[derive(Accounts)]
+pub struct FinalizeBreeding<'info> {
+    #[account(
+        mut,
+        seeds = [
+            BreedMachine::PREFIX,
+            breeding_machine.config.parents_candy_machine.as_ref(),
+            breeding_machine.config.reward_candy_machine.as_ref(),
+            breeding_machine.creator.as_ref(),
+        ],
+        bump = breeding_machine.bump
+    )]
+    pub breeding_machine: Account<'info, BreedMachine>,
+
+    #[account(
+        mut,
+        close = user_wallet,
+        constraint = breed_data.owner == user_wallet.key() @ BreedingError::InvalidBreedOwner,
+        seeds = [
+            BreedData::PREFIX,
+            breeding_machine.key().as_ref(),
+            BreedData::seed_mint(&breed_data.mints).as_ref(),
+        ],
+        bump
+    )]
+    pub breed_data: Account<'info, BreedData>,
+
+    #[account(
+        seeds = [b"whitelist_token", breeding_machine.key().as_ref()],
+        bump,
+    )]
+    pub whitelist_token: Box<Account<'info, Mint>>,
+
+    #[account(
+        mut,
+        associated_token::mint = whitelist_token,
+        associated_token::authority = breeding_machine
+    )]
+    pub whitelist_vault: Box<Account<'info, TokenAccount>>,
+
+    /// CHECK: Checked in `check_user_whitelist_ata` when the machine sends
+    /// whitelist tokens. Pass the user wallet when it mints the child NFT.
+    #[account(mut)]
+    pub user_whitelist_ata: UncheckedAccount<'info>,
+
+    /// CHECK: Checked in `mint_child` when the machine mints the child NFT,
+    /// the rest of the child accounts too. Pass the user wallet when it doesn't.
+    #[account(mut)]
+    pub child_mint: UncheckedAccount<'info>,
+
+    /// CHECK: See `child_mint`.
+    #[account(mut)]
+    pub child_metadata: UncheckedAccount<'info>,
+
+    /// CHECK: See `child_mint`.
+    #[account(mut)]
+    pub child_master_edition: UncheckedAccount<'info>,
+
+    /// CHECK: See `child_mint`.
+    #[account(mut)]
+    pub child_ata: UncheckedAccount<'info>,
+
+    /// CHECK: See `child_mint`.
+    pub collection_mint: UncheckedAccount<'info>,
+
+    /// CHECK: Checked by the token metadata program when verifying the child
+    /// collection. Pass the user wallet when the machine doesn't mint it.
+    pub collection_metadata: UncheckedAccount<'info>,
+
+    /// CHECK: See `collection_metadata`.
+    pub collection_master_edition: UncheckedAccount<'info>,
+
+    /// CHECK: Record of the child NFT, checked in `init_child_parent_record`.
+    /// Pass the user wallet when the machine doesn't mint it.
+    #[account(mut)]
+    pub child_parent_record: UncheckedAccount<'info>,
+
+    /// CHECK: Checked and created in `record_lineage`.
+    #[account(mut)]
+    pub lineage: UncheckedAccount<'info>,
+
+    /// CHECK: Token metadata program.
+    #[account(address = mpl_token_metadata::ID)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+
+    /// CHECK: Checked in `FeeEscrow::settle` when the breeding paid a token
+    /// fee. Pass the user wallet when it didn't.
+    #[account(mut)]
+    pub fee_token: UncheckedAccount<'info>,
+
+    /// CHECK: Checked in `FeeEscrow::settle` when the breeding paid a token
+    /// fee. Pass the user wallet when it didn't.
+    #[account(mut)]
+    pub fee_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Checked against the breed data when part of the token fee goes
+    /// to the treasury. Pass the user wallet when it doesn't.
+    #[account(mut)]
+    pub fee_treasury: UncheckedAccount<'info>,
+
+    /// CHECK: Checked against the breed data when part of the SOL fee goes to
+    /// the treasury. Pass the user wallet when it doesn't.
+    #[account(mut)]
+    pub sol_fee_treasury: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub user_wallet: Signer<'info>,
+
+    pub rent: Sysvar<'info, Rent>,
+    pub token_program: Program<'info, Token>,
+    pub system_program: Program<'info, System>,
+    pub associated_token_program: Program<'info, AssociatedToken>,
+
+    /// CHECK: Read in `randomness::slot_hash_at`.
+    #[account(address = sysvar::slot_hashes::ID)]
+    pub slot_hashes: UncheckedAccount<'info>,
+}
+
+impl<'info> FinalizeBreeding<'info> {
+    /// Seed of the outcome rolls, stored by `reveal_breeding` or read from the
+    /// hash of the reveal slot. None once the reveal slot left `SlotHashes`
+    /// before anyone revealed it.
+    pub fn outcome_seed(&self) -> Result<Option<[u8; 32]>> {
+        match self.breed_data.seed {
+            Some(seed) => Ok(Some(seed)),
+            None => randomness::outcome_seed(
+                &self.breed_data.commitment,
+                self.breed_data.commit_slot,
+                &self.slot_hashes,
+            ),
+        }
+    }
+
+    /// Rolls the outcome of the breeding with the odds it started with.
+    /// Breedings that don't roll don't need the reveal slot hash, and the
+    /// ones nobody revealed in time fail.
+    pub fn roll_success(&self) -> Result<bool> {
+        if !self.breed_data.rolls {
+            return Ok(true);
+        }
+
+        Ok(match self.outcome_seed()? {
+            Some(seed) => randomness::roll_success(&seed, self.breed_data.success_bps),
+            None => false,
+        })
+    }
+
+    /// Sends a whitelist token of a tier picked by weight among the tiers
+    /// with tokens left, and returns the tier. The weight of empty tiers goes
+    /// to the others. Only rolls when more than one tier can be picked, and
+    /// more than one could when the breeding started.
+    pub fn send_reward(
+        &self,
+        tiers: &[RewardTierAccounts<'_, 'info>],
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<u8> {
+        // Breedings started while the machine minted child NFTs didn't
+        // reserve a token, so they can't take one reserved by another.
+        if !self.breed_data.reserved_reward {
+            let rewards_left = RewardTierAccounts::rewards_left(&self.whitelist_vault, tiers)?;
+            require_gt!(
+                rewards_left,
+                self.breeding_machine.reserved,
+                BreedingError::RewardsSoldOut
+            );
+        }
+
+        let amounts = RewardTierAccounts::amounts(&self.whitelist_vault, tiers)?;
+        let weights = RewardTierAccounts::weights(&self.breeding_machine, &amounts);
+
+        // Breedings that had a single tier to pick from when they started
+        // never roll, even if the authority weighted other tiers since.
+        let rolls =
+            self.breed_data.rolls && weights.iter().filter(|weight| **weight > 0).count() > 1;
+        let picked = if rolls {
+            let seed = self.outcome_seed()?.ok_or(BreedingError::RevealExpired)?;
+            randomness::pick_tier(&seed, &weights)
+        } else {
+            weights.iter().position(|weight| *weight > 0)
+        }
+        // Tiers with tokens left but no weight, then the config one. When
+        // every tier is empty, the transfer fails.
+        .or_else(|| amounts.iter().position(|amount| *amount > 0))
+        .unwrap_or(0);
+
+        match picked {
+            0 => {
+                self.check_user_whitelist_ata()?;
+                anchor_spl::token::transfer(self.transfer_ctx().with_signer(signer_seeds), 1)?;
+            }
+            tier => self.send_tier_reward(&tiers[tier - 1], signer_seeds)?,
+        }
+
+        Ok(picked as u8)
+    }
+
+    /// Sends a whitelist token of an extra tier, creating the user ATA the
+    /// first time the user gets one.
+    fn send_tier_reward(
+        &self,
+        tier: &RewardTierAccounts<'_, 'info>,
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        let user_ata = tier.user_ata.ok_or(ErrorCode::AccountNotEnoughKeys)?;
+        require_keys_eq!(
+            user_ata.key(),
+            get_associated_token_address(&self.user_wallet.key(), tier.mint.key),
+            ErrorCode::ConstraintAssociated
+        );
+
+        if user_ata.data_is_empty() {
+            let accounts = Create {
+                payer: self.user_wallet.to_account_info(),
+                associated_token: user_ata.clone(),
+                authority: self.user_wallet.to_account_info(),
+                mint: tier.mint.clone(),
+                system_program: self.system_program.to_account_info(),
+                token_program: self.token_program.to_account_info(),
+                rent: self.rent.to_account_info(),
+            };
+            let cpi = CpiContext::new(self.associated_token_program.to_account_info(), accounts);
+            anchor_spl::associated_token::create(cpi)?;
+        }
+
+        let accounts = Transfer {
+            from: tier.vault.clone(),
+            to: user_ata.clone(),
+            authority: self.breeding_machine.to_account_info(),
         };
-        self.close_parent_vaults(signer_seeds)
+        let cpi = CpiContext::new(self.token_program.to_account_info(), accounts);
+        anchor_spl::token::transfer(cpi.with_signer(signer_seeds), 1)
+    }
+
+    pub fn unlock_parents(
+        &self,
+        parents: &[ParentAccounts<'_, 'info>],
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        self.parent_lock().release(
+            parents,
+            self.breed_data.burn_parents,
+            self.breed_data.lock_mode,
+            signer_seeds,
+        )
+    }
+
+    pub fn parent_lock(&self) -> ParentLock<'info> {
+        ParentLock {
+            breed_data: self.breed_data.to_account_info(),
+            user_wallet: self.user_wallet.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+            token_metadata_program: self.token_metadata_program.to_account_info(),
+        }
+    }
+
+    pub fn fee_escrow(&self) -> FeeEscrow<'info> {
+        FeeEscrow {
+            breed_data: self.breed_data.to_account_info(),
+            fee_token: self.fee_token.to_account_info(),
+            fee_vault: self.fee_vault.to_account_info(),
+            // Nothing is refunded on finalize.
+            fee_payer_ata: self.user_wallet.to_account_info(),
+            fee_treasury: self.fee_treasury.to_account_info(),
+            sol_fee_treasury: self.sol_fee_treasury.to_account_info(),
+            user_wallet: self.user_wallet.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+        }
     }
 
     pub fn transfer_ctx(&self) -> CpiContext<'_, '_, '_, 'info, Transfer<'info>> {
@@ -408,8 +1931,237 @@ impl<'info> FinalizeBreeding<'info> {
 
         CpiContext::new(self.token_program.to_account_info(), accounts)
     }
+
+    pub fn check_user_whitelist_ata(&self) -> Result<()> {
+        require_keys_eq!(
+            self.user_whitelist_ata.key(),
+            get_associated_token_address(&self.user_wallet.key(), &self.whitelist_token.key()),
+            ErrorCode::ConstraintAssociated
+        );
+
+        Ok(())
+    }
+
+    /// Writes the lineage of the child, keyed by its mint when the machine
+    /// mints it, or by the breed id otherwise.
+    pub fn record_lineage(&self, generation: u16, now: i64) -> Result<()> {
+        let machine = self.breeding_machine.key();
+        let breed_id = self.breeding_machine.born;
+        let breed_id_bytes = breed_id.to_le_bytes();
+        let child_mint = self
+            .breeding_machine
+            .child_nft
+            .as_ref()
+            .map(|_| self.child_mint.key());
+
+        let mut seeds: Vec<&[u8]> = match &child_mint {
+            Some(child_mint) => vec![Lineage::PREFIX, child_mint.as_ref()],
+            None => vec![Lineage::PREFIX, machine.as_ref(), breed_id_bytes.as_ref()],
+        };
+        let (address, bump) = Pubkey::find_program_address(&seeds, &crate::ID);
+        require_keys_eq!(self.lineage.key(), address, ErrorCode::ConstraintSeeds);
+
+        let bump_seed = [bump];
+        seeds.push(&bump_seed);
+        create_pda_account(
+            &self.user_wallet.to_account_info(),
+            &self.lineage.to_account_info(),
+            &self.system_program.to_account_info(),
+            8 + Lineage::LEN,
+            &crate::ID,
+            &seeds,
+        )?;
+
+        let lineage = Lineage {
+            machine,
+            breed_id,
+            child_mint,
+            parents: self.breed_data.mints.clone(),
+            generation,
+            timestamp: now,
+            bump,
+        };
+
+        let info = self.lineage.to_account_info();
+        let mut data = info.try_borrow_mut_data()?;
+        let mut writer: &mut [u8] = &mut data;
+        lineage.try_serialize(&mut writer)
+    }
+
+    /// Creates the record of the child NFT, so its generation is known when
+    /// it breeds in turn.
+    pub fn init_child_parent_record(&self, generation: u16) -> Result<()> {
+        create_parent_record(
+            &self.child_parent_record.to_account_info(),
+            &self.child_mint.key(),
+            generation,
+            &self.user_wallet.to_account_info(),
+            &self.system_program.to_account_info(),
+        )
+    }
+
+    /// Mints the child NFT to the user, with the machine as update authority
+    /// and verified creator.
+    pub fn mint_child(&self, child_nft: &ChildNft, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+        let machine = self.breeding_machine.key();
+        let machine_info = self.breeding_machine.to_account_info();
+        let user_wallet = self.user_wallet.to_account_info();
+        let index = self.breeding_machine.born.to_le_bytes();
+
+        let (child_mint, bump) = Pubkey::find_program_address(
+            &[CHILD_MINT_PREFIX, machine.as_ref(), &index],
+            &crate::ID,
+        );
+        require_keys_eq!(
+            self.child_mint.key(),
+            child_mint,
+            ErrorCode::ConstraintSeeds
+        );
+        require_keys_eq!(
+            self.child_metadata.key(),
+            find_metadata_account(&child_mint).0,
+            ErrorCode::ConstraintSeeds
+        );
+        require_keys_eq!(
+            self.child_master_edition.key(),
+            find_master_edition_account(&child_mint).0,
+            ErrorCode::ConstraintSeeds
+        );
+        require_keys_eq!(
+            self.collection_mint.key(),
+            child_nft.collection,
+            BreedingError::InvalidRewardCollection
+        );
+
+        // Create the child mint.
+        create_pda_account(
+            &user_wallet,
+            &self.child_mint.to_account_info(),
+            &self.system_program.to_account_info(),
+            spl_token::state::Mint::LEN,
+            &Token::id(),
+            &[CHILD_MINT_PREFIX, machine.as_ref(), &index, &[bump]],
+        )?;
+
+        let accounts = InitializeMint {
+            mint: self.child_mint.to_account_info(),
+            rent: self.rent.to_account_info(),
+        };
+        let cpi = CpiContext::new(self.token_program.to_account_info(), accounts);
+        anchor_spl::token::initialize_mint(cpi, 0, &machine, Some(&machine))?;
+
+        // Mint it to the user.
+        let accounts = Create {
+            payer: user_wallet.clone(),
+            associated_token: self.child_ata.to_account_info(),
+            authority: user_wallet.clone(),
+            mint: self.child_mint.to_account_info(),
+            system_program: self.system_program.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+            rent: self.rent.to_account_info(),
+        };
+        let cpi = CpiContext::new(self.associated_token_program.to_account_info(), accounts);
+        anchor_spl::associated_token::create(cpi)?;
+
+        let accounts = MintTo {
+            mint: self.child_mint.to_account_info(),
+            to: self.child_ata.to_account_info(),
+            authority: machine_info.clone(),
+        };
+        let cpi = CpiContext::new(self.token_program.to_account_info(), accounts);
+        anchor_spl::token::mint_to(cpi.with_signer(signer_seeds), 1)?;
+
+        // Numbered from 1, as candy machine items.
+        let (name, uri) = child_nft.metadata(self.breeding_machine.born + 1);
+
+        invoke_signed(
+            &create_metadata_accounts_v2(
+                mpl_token_metadata::ID,
+                self.child_metadata.key(),
+                child_mint,
+                machine,
+                user_wallet.key(),
+                machine,
+                name,
+                child_nft.symbol.clone(),
+                uri,
+                Some(vec![Creator {
+                    address: machine,
+                    verified: true,
+                    share: 100,
+                }]),
+                child_nft.seller_fee_basis_points,
+                true,
+                true,
+                Some(Collection {
+                    verified: false,
+                    key: child_nft.collection,
+                }),
+                None,
+            ),
+            &[
+                self.child_metadata.to_account_info(),
+                self.child_mint.to_account_info(),
+                machine_info.clone(),
+                user_wallet.clone(),
+                self.system_program.to_account_info(),
+                self.rent.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        // Takes over the mint authority, so no more copies can be minted.
+        invoke_signed(
+            &create_master_edition_v3(
+                mpl_token_metadata::ID,
+                self.child_master_edition.key(),
+                child_mint,
+                machine,
+                machine,
+                self.child_metadata.key(),
+                user_wallet.key(),
+                Some(0),
+            ),
+            &[
+                self.child_master_edition.to_account_info(),
+                self.child_mint.to_account_info(),
+                machine_info.clone(),
+                user_wallet.clone(),
+                self.child_metadata.to_account_info(),
+                self.token_program.to_account_info(),
+                self.system_program.to_account_info(),
+                self.rent.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        invoke_signed(
+            &mpl_token_metadata::instruction::verify_collection(
+                mpl_token_metadata::ID,
+                self.child_metadata.key(),
+                machine,
+                user_wallet.key(),
+                self.collection_mint.key(),
+                self.collection_metadata.key(),
+                self.collection_master_edition.key(),
+                None,
+            ),
+            &[
+                self.child_metadata.to_account_info(),
+                machine_info,
+                user_wallet,
+                self.collection_mint.to_account_info(),
+                self.collection_metadata.to_account_info(),
+                self.collection_master_edition.to_account_info(),
+            ],
+            signer_seeds,
+        )?;
+
+        Ok(())
+    }
 }
 
+/// Parent accounts are passed in the remaining accounts, see `ParentAccounts`.
 #[derive(Accounts)]
 pub struct CancelBreeding<'info> {
     #[account(mut)]
@@ -418,99 +2170,138 @@ pub struct CancelBreeding<'info> {
     #[account(
         mut,
         close = user_wallet,
+        constraint = breed_data.owner == user_wallet.key() @ BreedingError::InvalidBreedOwner,
         seeds = [
             BreedData::PREFIX,
             breeding_machine.key().as_ref(),
-            mint_parent_a.key().as_ref(),
-            mint_parent_b.key().as_ref(),
+            BreedData::seed_mint(&breed_data.mints).as_ref(),
         ],
         bump
     )]
     pub breed_data: Account<'info, BreedData>,
 
-    #[account(address = breed_data.mint_a)]
-    pub mint_parent_a: Account<'info, Mint>,
-    #[account(address = breed_data.mint_b)]
-    pub mint_parent_b: Account<'info, Mint>,
+    /// CHECK: Checked in `FeeEscrow::settle` when the breeding paid a token
+    /// fee. Pass the user wallet when it didn't.
+    #[account(mut)]
+    pub fee_token: UncheckedAccount<'info>,
 
-    #[account(
-        mut,
-        associated_token::mint = mint_parent_a,
-        associated_token::authority = user_wallet
-    )]
-    pub user_ata_parent_a: Box<Account<'info, TokenAccount>>,
+    /// CHECK: Checked in `FeeEscrow::settle` when the breeding paid a token
+    /// fee. Pass the user wallet when it didn't.
+    #[account(mut)]
+    pub fee_vault: UncheckedAccount<'info>,
+
+    /// CHECK: Receives the refunded token fee. Pass the user wallet when there
+    /// is nothing to refund.
+    #[account(mut)]
+    pub fee_payer_ata: UncheckedAccount<'info>,
+
+    /// CHECK: Checked against the breed data when part of the token fee goes
+    /// to the treasury. Pass the user wallet when it doesn't.
+    #[account(mut)]
+    pub fee_treasury: UncheckedAccount<'info>,
+
+    /// CHECK: Checked against the breed data when part of the SOL fee goes to
+    /// the treasury. Pass the user wallet when it doesn't.
+    #[account(mut)]
+    pub sol_fee_treasury: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub user_wallet: Signer<'info>,
+
+    pub token_program: Program<'info, Token>,
+
+    /// CHECK: Token metadata program, thawing the parents in freeze mode.
+    #[account(address = mpl_token_metadata::ID)]
+    pub token_metadata_program: UncheckedAccount<'info>,
+}
+
+impl<'info> CancelBreeding<'info> {
+    pub fn unlock_parents(
+        &self,
+        parents: &[ParentAccounts<'_, 'info>],
+        signer_seeds: &[&[&[u8]]],
+    ) -> Result<()> {
+        self.parent_lock()
+            .release(parents, false, self.breed_data.lock_mode, signer_seeds)
+    }
+
+    pub fn parent_lock(&self) -> ParentLock<'info> {
+        ParentLock {
+            breed_data: self.breed_data.to_account_info(),
+            user_wallet: self.user_wallet.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+            token_metadata_program: self.token_metadata_program.to_account_info(),
+        }
+    }
 
+    pub fn fee_escrow(&self) -> FeeEscrow<'info> {
+        FeeEscrow {
+            breed_data: self.breed_data.to_account_info(),
+            fee_token: self.fee_token.to_account_info(),
+            fee_vault: self.fee_vault.to_account_info(),
+            fee_payer_ata: self.fee_payer_ata.to_account_info(),
+            fee_treasury: self.fee_treasury.to_account_info(),
+            sol_fee_treasury: self.sol_fee_treasury.to_account_info(),
+            user_wallet: self.user_wallet.to_account_info(),
+            token_program: self.token_program.to_account_info(),
+        }
+    }
+}
+
+#[derive(Accounts)]
+pub struct CloseMachine<'info> {
     #[account(
         mut,
-        associated_token::mint = mint_parent_b,
-        associated_token::authority = user_wallet
+        has_one = authority,
+        close = authority,
+        seeds = [
+            BreedMachine::PREFIX,
+            breeding_machine.config.parents_candy_machine.as_ref(),
+            breeding_machine.config.reward_candy_machine.as_ref(),
+            breeding_machine.creator.as_ref(),
+        ],
+        bump = breeding_machine.bump
     )]
-    pub user_ata_parent_b: Box<Account<'info, TokenAccount>>,
+    pub breeding_machine: Account<'info, BreedMachine>,
 
     #[account(
         mut,
-        associated_token::mint = mint_parent_a,
-        associated_token::authority = breed_data
+        seeds = [b"whitelist_token", breeding_machine.key().as_ref()],
+        bump,
     )]
-    pub vault_ata_parent_a: Box<Account<'info, TokenAccount>>,
+    pub whitelist_token: Box<Account<'info, Mint>>,
 
     #[account(
         mut,
-        associated_token::mint = mint_parent_b,
-        associated_token::authority = breed_data
+        associated_token::mint = whitelist_token,
+        associated_token::authority = breeding_machine
     )]
-    pub vault_ata_parent_b: Box<Account<'info, TokenAccount>>,
+    pub whitelist_vault: Box<Account<'info, TokenAccount>>,
 
     #[account(mut)]
-    pub user_wallet: Signer<'info>,
+    pub authority: Signer<'info>,
 
     pub token_program: Program<'info, Token>,
 }
 
-impl<'info> CancelBreeding<'info> {
-    fn return_parents(&self, signer_seeds: &[&[&[u8]]]) -> Result<()> {
-        let accounts = Transfer {
-            from: self.vault_ata_parent_a.to_account_info(),
-            to: self.user_ata_parent_a.to_account_info(),
-            authority: self.breed_data.to_account_info(),
-        };
-        let cpi = CpiContext::new(self.token_program.to_account_info(), accounts);
-        anchor_spl::token::transfer(cpi.with_signer(signer_seeds), 1)?;
-
-        let accounts = Transfer {
-            from: self.vault_ata_parent_b.to_account_info(),
-            to: self.user_ata_parent_b.to_account_info(),
-            authority: self.breed_data.to_account_info(),
+impl<'info> CloseMachine<'info> {
+    pub fn burn_ctx(&self) -> CpiContext<'_, '_, '_, 'info, Burn<'info>> {
+        let accounts = Burn {
+            from: self.whitelist_vault.to_account_info(),
+            mint: self.whitelist_token.to_account_info(),
+            authority: self.breeding_machine.to_account_info(),
         };
-        let cpi = CpiContext::new(self.token_program.to_account_info(), accounts);
-        anchor_spl::token::transfer(cpi.with_signer(signer_seeds), 1)?;
 
-        Ok(())
+        CpiContext::new(self.token_program.to_account_info(), accounts)
     }
 
-    fn close_parent_vaults(&self, signer_seeds: &[&[&[u8]]]) -> Result<()> {
+    pub fn close_vault_ctx(&self) -> CpiContext<'_, '_, '_, 'info, CloseAccount<'info>> {
         let accounts = CloseAccount {
-            account: self.vault_ata_parent_a.to_account_info(),
-            destination: self.breed_data.to_account_info(),
-            authority: self.breed_data.to_account_info(),
-        };
-        let cpi = CpiContext::new(self.token_program.to_account_info(), accounts);
-        anchor_spl::token::close_account(cpi.with_signer(signer_seeds))?;
-
-        let accounts = CloseAccount {
-            account: self.vault_ata_parent_b.to_account_info(),
-            destination: self.breed_data.to_account_info(),
-            authority: self.breed_data.to_account_info(),
+            account: self.whitelist_vault.to_account_info(),
+            destination: self.authority.to_account_info(),
+            authority: self.breeding_machine.to_account_info(),
         };
-        let cpi = CpiContext::new(self.token_program.to_account_info(), accounts);
-        anchor_spl::token::close_account(cpi.with_signer(signer_seeds))?;
 
-        Ok(())
-    }
-
-    pub fn unlock_parents(&self, signer_seeds: &[&[&[u8]]]) -> Result<()> {
-        self.return_parents(signer_seeds)?;
-        self.close_parent_vaults(signer_seeds)?;
-        Ok(())
+        CpiContext::new(self.token_program.to_account_info(), accounts)
     }
 }
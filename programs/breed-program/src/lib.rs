@@ -1,10 +1,13 @@
 use anchor_lang::prelude::*;
-use solutils::charge;
+use std::convert::TryFrom;
 
 declare_id!("9zjxuHUgiVpB8Ex7QYLgYBTqEZaLR92dKxgPmdcXktrK");
 
+pub mod events;
 pub mod instructions;
+pub mod randomness;
 
+use events::*;
 use instructions::*;
 
 #[program]
@@ -15,32 +18,41 @@ pub mod breed_program {
     use super::*;
 
     pub fn create_machine(ctx: Context<InitializeBreedMachine>, config: BreedConfig) -> Result<()> {
-        let machine = BreedMachine::new(ctx.accounts.authority.key(), config);
-        ctx.accounts.breeding_machine.set_inner(machine);
+        config.validate()?;
 
         let bump = *ctx.bumps.get("breeding_machine").unwrap();
-        let machine_seeds = &[
-            BreedMachine::PREFIX,
-            config.parents_candy_machine.as_ref(),
-            config.reward_candy_machine.as_ref(),
-            ctx.accounts.breeding_machine.authority.as_ref(),
-            &[bump],
-        ];
+        let machine = BreedMachine::new(ctx.accounts.authority.key(), bump, config);
+        ctx.accounts.breeding_machine.set_inner(machine);
+
+        let machine_seeds = ctx.accounts.breeding_machine.seeds();
 
         // Mint the whitelist supply.
         anchor_spl::token::mint_to(
-            ctx.accounts.mint_to_ctx().with_signer(&[&*machine_seeds]),
+            ctx.accounts.mint_to_ctx().with_signer(&[&machine_seeds]),
             config.reward_supply,
         )?;
 
-        // Unset mint authority.
-        anchor_spl::token::set_authority(
-            ctx.accounts
-                .set_authority_ctx()
-                .with_signer(&[&*machine_seeds]),
-            AuthorityType::MintTokens,
-            None,
-        )?;
+        // Unset mint authority, unless the machine can replenish the rewards.
+        if !config.mint_capped {
+            anchor_spl::token::set_authority(
+                ctx.accounts
+                    .set_authority_ctx()
+                    .with_signer(&[&machine_seeds]),
+                AuthorityType::MintTokens,
+                None,
+            )?;
+        }
+
+        let machine = &ctx.accounts.breeding_machine;
+        emit!(MachineCreated {
+            machine: machine.key(),
+            authority: machine.authority,
+            creator: machine.creator,
+            parents_candy_machine: config.parents_candy_machine,
+            reward_candy_machine: config.reward_candy_machine,
+            reward_supply: config.reward_supply,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
         Ok(())
     }
@@ -56,37 +68,120 @@ pub mod breed_program {
             .initialization_fee_price
             .unwrap_or(config.initialization_fee_price);
         config.burn_parents = args.burn_parents.unwrap_or(config.burn_parents);
+        config.parents_collection = args.parents_collection.unwrap_or(config.parents_collection);
+        config.parent_validation = args.parent_validation.unwrap_or(config.parent_validation);
+        config.parents_count = args.parents_count.unwrap_or(config.parents_count);
+        config.breeding_cooldown = args.breeding_cooldown.unwrap_or(config.breeding_cooldown);
+        config.max_breeds_per_parent = args
+            .max_breeds_per_parent
+            .unwrap_or(config.max_breeds_per_parent);
+        config.fee_mode = args.fee_mode.unwrap_or(config.fee_mode);
+        config.fee_treasury = args.fee_treasury.unwrap_or(config.fee_treasury);
+        config.sol_fee_lamports = args.sol_fee_lamports.unwrap_or(config.sol_fee_lamports);
+        config.sol_fee_treasury = args.sol_fee_treasury.unwrap_or(config.sol_fee_treasury);
+        config.cancel_policy = args.cancel_policy.unwrap_or(config.cancel_policy);
+        config.lock_mode = args.lock_mode.unwrap_or(config.lock_mode);
+        config.success_bps = args.success_bps.unwrap_or(config.success_bps);
+
+        config.validate()?;
+
+        let machine = &ctx.accounts.breeding_machine;
+        emit!(ConfigUpdated {
+            machine: machine.key(),
+            authority: machine.authority,
+            config: machine.config,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
 
         Ok(())
     }
 
     #[access_control(InitializeBreed::charge_token_fee(&ctx, ctx.accounts.breeding_machine.config.initialization_fee_price))]
-    #[access_control(InitializeBreed::validate_nfts(&ctx))]
-    pub fn initialize_breeding(ctx: Context<InitializeBreed>) -> Result<()> {
+    #[access_control(InitializeBreed::charge_sol_fee(&ctx, ctx.accounts.breeding_machine.config.sol_fee_lamports))]
+    #[access_control(InitializeBreed::validate_nfts(&ctx, &parents))]
+    #[access_control(InitializeBreed::validate_parent_records(&ctx, &parents))]
+    pub fn initialize_breeding<'info>(
+        ctx: Context<'_, '_, '_, 'info, InitializeBreed<'info>>,
+        parents: Vec<Pubkey>,
+    ) -> Result<()> {
         let owner = ctx.accounts.user_wallet.key();
-        let mint_parent_a = ctx.accounts.mint_parent_a.key();
-        let mint_parent_b = ctx.accounts.mint_parent_b.key();
+
+        // Parent accounts, then the accounts of the extra reward tiers.
+        let (parent_infos, tier_infos) =
+            ParentAccounts::split(ctx.remaining_accounts, ParentAccounts::INIT_LEN, &parents);
+
+        let parent_accounts =
+            ParentAccounts::load(parent_infos, ParentAccounts::INIT_LEN, &parents)?;
+        let tiers = RewardTierAccounts::load(
+            tier_infos,
+            RewardTierAccounts::INIT_LEN,
+            &ctx.accounts.breeding_machine,
+        )?;
+
+        // Records are created on the parents first breeding.
+        ctx.accounts.init_parent_records(&parent_accounts)?;
 
         let breed_account = BreedData::new(
+            &ctx.accounts.breed_data.key(),
             ctx.accounts.breeding_machine.authority,
             owner,
-            mint_parent_a,
-            mint_parent_b,
+            parents,
+            &ctx.accounts.breeding_machine.config,
         )?;
 
         ctx.accounts.breed_data.set_inner(breed_account);
-        ctx.accounts.lock_parents()?;
+
+        // Reserve the whitelist token sent on finalize, so it can't run out
+        // while the breeding is in progress.
+        ctx.accounts.breed_data.reserved_reward = ctx.accounts.reserve_reward(&tiers)?;
+        ctx.accounts.breed_data.rolls = ctx.accounts.rolls_outcome(&tiers)?;
+
+        let breed_data_bump = *ctx.bumps.get("breed_data").unwrap();
+        let machine_key = ctx.accounts.breeding_machine.key();
+        let seed_mint = BreedData::seed_mint(&ctx.accounts.breed_data.mints);
+
+        let breed_data_seeds = &[
+            BreedData::PREFIX,
+            machine_key.as_ref(),
+            seed_mint.as_ref(),
+            &[breed_data_bump], // must come last
+        ];
+
+        ctx.accounts
+            .lock_parents(&parent_accounts, &[breed_data_seeds])?;
+
+        // Increment in progress counter.
+        ctx.accounts.breeding_machine.in_progress = ctx
+            .accounts
+            .breeding_machine
+            .in_progress
+            .checked_add(1)
+            .ok_or(BreedingError::ArithmeticError)?;
 
         msg!("BreedingProgram: Breeding initialized.");
         msg!("BreedingProgram: Parents locked.");
 
+        let breed_data = &ctx.accounts.breed_data;
+        emit!(BreedingStarted {
+            machine: ctx.accounts.breeding_machine.key(),
+            breed_data: breed_data.key(),
+            owner,
+            parents: breed_data.mints.clone(),
+            fee_paid: breed_data.fee_paid,
+            sol_fee_paid: breed_data.sol_fee_paid,
+            in_progress: ctx.accounts.breeding_machine.in_progress,
+            timestamp: breed_data.timestamp,
+        });
+
         Ok(())
     }
 
-    pub fn finalize_breeding(ctx: Context<FinalizeBreeding>) -> Result<()> {
+    pub fn finalize_breeding<'info>(
+        ctx: Context<'_, '_, '_, 'info, FinalizeBreeding<'info>>,
+    ) -> Result<()> {
         let now_timestamp = Clock::get()?.unix_timestamp as u64;
         let breed_start_timestamp = ctx.accounts.breed_data.timestamp as u64;
-        let breeding_time = ctx.accounts.breeding_machine.config.breeding_time;
+        let breeding_time = ctx.accounts.breed_data.breeding_time;
 
         require_gte!(
             now_timestamp,
@@ -94,91 +189,827 @@ pub mod breed_program {
             BreedingError::StillInProgress
         );
 
-        // Unlock parents (burn or transfer back)
+        // Unlock parents (burn, transfer back or thaw)
         let breed_data_bump = *ctx.bumps.get("breed_data").unwrap();
 
-        ctx.accounts.unlock_parents(&[&[
+        let machine_key = ctx.accounts.breeding_machine.key();
+        let seed_mint = BreedData::seed_mint(&ctx.accounts.breed_data.mints);
+
+        let breed_data_seeds = &[
             BreedData::PREFIX,
-            ctx.accounts.breeding_machine.key().as_ref(),
-            ctx.accounts.mint_parent_a.key().as_ref(),
-            ctx.accounts.mint_parent_b.key().as_ref(),
+            machine_key.as_ref(),
+            seed_mint.as_ref(),
             &[breed_data_bump], // must come last
-        ]])?;
+        ];
 
-        // Send 1 reward whitelist token to the user.
-        let machine_bump = *ctx.bumps.get("breeding_machine").unwrap();
-        let config = ctx.accounts.breeding_machine.config;
+        // Parent accounts, then the accounts of the extra reward tiers.
+        let (parent_infos, tier_infos) = ParentAccounts::split(
+            ctx.remaining_accounts,
+            ParentAccounts::FINALIZE_LEN,
+            &ctx.accounts.breed_data.mints,
+        );
 
-        let machine_seeds = &[
-            BreedMachine::PREFIX,
-            config.parents_candy_machine.as_ref(),
-            config.reward_candy_machine.as_ref(),
-            ctx.accounts.breeding_machine.authority.as_ref(),
-            &[machine_bump],
-        ];
+        let parents = ParentAccounts::load(
+            parent_infos,
+            ParentAccounts::FINALIZE_LEN,
+            &ctx.accounts.breed_data.mints,
+        )?;
+        ctx.accounts.unlock_parents(&parents, &[breed_data_seeds])?;
 
-        anchor_spl::token::transfer(
-            ctx.accounts.transfer_ctx().with_signer(&[&*machine_seeds]),
-            1,
+        let tiers = RewardTierAccounts::load(
+            tier_infos,
+            RewardTierAccounts::FINALIZE_LEN,
+            &ctx.accounts.breeding_machine,
         )?;
 
+        // Update parents breeding history.
+        let now = Clock::get()?.unix_timestamp;
+        let mut parents_generation = 0;
+        for parent in &parents {
+            let mut record = parent.parent_record()?;
+            record.record_breed(now)?;
+            record.exit(&crate::ID)?;
+            parents_generation = parents_generation.max(record.generation);
+        }
+        let generation = parents_generation
+            .checked_add(1)
+            .ok_or(BreedingError::ArithmeticError)?;
+
+        let machine_seeds = ctx.accounts.breeding_machine.seeds();
+
+        // Failed breedings still unlock the parents and settle the fees, but
+        // no child is born.
+        let succeeded = ctx.accounts.roll_success()?;
+        let mut reward_tier = None;
+
+        if succeeded {
+            match &ctx.accounts.breeding_machine.child_nft {
+                // Mint the child NFT to the user.
+                Some(child_nft) => {
+                    ctx.accounts.mint_child(child_nft, &[&machine_seeds])?;
+                    ctx.accounts.init_child_parent_record(generation)?;
+                }
+                // Send 1 reward whitelist token to the user.
+                None => {
+                    reward_tier = Some(ctx.accounts.send_reward(&tiers, &[&machine_seeds])?);
+                }
+            }
+
+            // Link the child to its parents.
+            ctx.accounts.record_lineage(generation, now)?;
+        }
+
+        // Settle the escrowed fees with the fee mode the breeding started with.
+        ctx.accounts
+            .fee_escrow()
+            .settle(&ctx.accounts.breed_data, 0, 0, &[breed_data_seeds])?;
+
         // Increment bred counter.
         ctx.accounts.breeding_machine.bred = ctx
             .accounts
             .breeding_machine
             .bred
-            .checked_add(2)
+            .checked_add(parents.len() as u64)
             .ok_or(BreedingError::ArithmeticError)?;
 
         // Increment born counter.
-        ctx.accounts.breeding_machine.born = ctx
+        if succeeded {
+            ctx.accounts.breeding_machine.born = ctx
+                .accounts
+                .breeding_machine
+                .born
+                .checked_add(1)
+                .ok_or(BreedingError::ArithmeticError)?;
+        }
+
+        // Release the reserved whitelist token, sent or not.
+        if ctx.accounts.breed_data.reserved_reward {
+            ctx.accounts.breeding_machine.release_reward()?;
+        }
+
+        // Decrement in progress counter.
+        ctx.accounts.breeding_machine.in_progress = ctx
             .accounts
             .breeding_machine
-            .born
-            .checked_add(1)
+            .in_progress
+            .checked_sub(1)
             .ok_or(BreedingError::ArithmeticError)?;
 
+        let machine = &ctx.accounts.breeding_machine;
+        let breed_data = &ctx.accounts.breed_data;
+        emit!(BreedingFinalized {
+            machine: machine.key(),
+            breed_data: breed_data.key(),
+            owner: breed_data.owner,
+            parents: breed_data.mints.clone(),
+            parents_burned: breed_data.burn_parents,
+            succeeded,
+            child_mint: machine
+                .child_nft
+                .as_ref()
+                .filter(|_| succeeded)
+                .map(|_| ctx.accounts.child_mint.key()),
+            lineage: ctx.accounts.lineage.key(),
+            reward_tier,
+            generation,
+            bred: machine.bred,
+            born: machine.born,
+            in_progress: machine.in_progress,
+            timestamp: now,
+        });
+
         Ok(())
     }
 
-    pub fn cancel_breeding(ctx: Context<CancelBreeding>) -> Result<()> {
+    pub fn cancel_breeding<'info>(
+        ctx: Context<'_, '_, '_, 'info, CancelBreeding<'info>>,
+    ) -> Result<()> {
+        // Users could otherwise cancel every breeding they see failing, and
+        // start again for free until one succeeds.
+        let breed_data = &ctx.accounts.breed_data;
+        require!(
+            !breed_data.rolls
+                || randomness::before_reveal(breed_data.commit_slot, Clock::get()?.slot),
+            BreedingError::OutcomeRevealed
+        );
+
         let breed_data_bump = *ctx.bumps.get("breed_data").unwrap();
 
-        ctx.accounts.unlock_parents(&[&[
+        let machine_key = ctx.accounts.breeding_machine.key();
+        let seed_mint = BreedData::seed_mint(&ctx.accounts.breed_data.mints);
+
+        let breed_data_seeds = &[
             BreedData::PREFIX,
-            ctx.accounts.breeding_machine.key().as_ref(),
-            ctx.accounts.mint_parent_a.key().as_ref(),
-            ctx.accounts.mint_parent_b.key().as_ref(),
+            machine_key.as_ref(),
+            seed_mint.as_ref(),
             &[breed_data_bump], // must come last
-        ]])?;
+        ];
+
+        let parents = ParentAccounts::load(
+            ctx.remaining_accounts,
+            ParentAccounts::CANCEL_LEN,
+            &ctx.accounts.breed_data.mints,
+        )?;
+        ctx.accounts.unlock_parents(&parents, &[breed_data_seeds])?;
+
+        // Refund the escrowed fees with the policy the breeding started with.
+        let breed_data = &ctx.accounts.breed_data;
+        let now = Clock::get()?.unix_timestamp;
+        let elapsed = now.saturating_sub(breed_data.timestamp).max(0) as u64;
+
+        let policy = breed_data.cancel_policy;
+        let token_refund = policy.refund(breed_data.fee_paid, elapsed, breed_data.breeding_time);
+        let sol_refund = policy.refund(breed_data.sol_fee_paid, elapsed, breed_data.breeding_time);
+
+        ctx.accounts.fee_escrow().settle(
+            breed_data,
+            token_refund,
+            sol_refund,
+            &[breed_data_seeds],
+        )?;
+
+        // Release the reserved whitelist token.
+        if ctx.accounts.breed_data.reserved_reward {
+            ctx.accounts.breeding_machine.release_reward()?;
+        }
+
+        // Decrement in progress counter.
+        ctx.accounts.breeding_machine.in_progress = ctx
+            .accounts
+            .breeding_machine
+            .in_progress
+            .checked_sub(1)
+            .ok_or(BreedingError::ArithmeticError)?;
+
+        let breed_data = &ctx.accounts.breed_data;
+        emit!(BreedingCancelled {
+            machine: ctx.accounts.breeding_machine.key(),
+            breed_data: breed_data.key(),
+            owner: breed_data.owner,
+            parents: breed_data.mints.clone(),
+            token_refund,
+            sol_refund,
+            in_progress: ctx.accounts.breeding_machine.in_progress,
+            timestamp: now,
+        });
+
+        Ok(())
+    }
+
+    pub fn close_machine<'info>(
+        ctx: Context<'_, '_, '_, 'info, CloseMachine<'info>>,
+    ) -> Result<()> {
+        require_eq!(
+            ctx.accounts.breeding_machine.in_progress,
+            0,
+            BreedingError::ActiveBreedings
+        );
+
+        let machine_seeds = ctx.accounts.breeding_machine.seeds();
+
+        let tiers = RewardTierAccounts::load(
+            ctx.remaining_accounts,
+            RewardTierAccounts::CLOSE_LEN,
+            &ctx.accounts.breeding_machine,
+        )?;
+        let burned = RewardTierAccounts::rewards_left(&ctx.accounts.whitelist_vault, &tiers)?;
+
+        // Burn the whitelist tokens that were never claimed.
+        let leftover = ctx.accounts.whitelist_vault.amount;
+        if leftover > 0 {
+            anchor_spl::token::burn(
+                ctx.accounts.burn_ctx().with_signer(&[&machine_seeds]),
+                leftover,
+            )?;
+        }
+
+        // Close the vault. The SPL token program can't close mints, so the
+        // whitelist token mint stays around with the claimed supply.
+        anchor_spl::token::close_account(
+            ctx.accounts
+                .close_vault_ctx()
+                .with_signer(&[&machine_seeds]),
+        )?;
+
+        // Same for the other reward tiers.
+        for tier in &tiers {
+            tier.burn_and_close(
+                &ctx.accounts.breeding_machine.to_account_info(),
+                &ctx.accounts.authority.to_account_info(),
+                &ctx.accounts.token_program.to_account_info(),
+                &[&machine_seeds],
+            )?;
+        }
+
+        msg!("BreedingProgram: Machine closed.");
+
+        let machine = &ctx.accounts.breeding_machine;
+        emit!(MachineClosed {
+            machine: machine.key(),
+            authority: machine.authority,
+            burned,
+            bred: machine.bred,
+            born: machine.born,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn pause_machine(ctx: Context<ToggleMachine>) -> Result<()> {
+        ctx.accounts.breeding_machine.paused = true;
+
+        msg!("BreedingProgram: Machine paused.");
+
+        let machine = &ctx.accounts.breeding_machine;
+        emit!(MachinePaused {
+            machine: machine.key(),
+            authority: machine.authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn resume_machine(ctx: Context<ToggleMachine>) -> Result<()> {
+        ctx.accounts.breeding_machine.paused = false;
+
+        msg!("BreedingProgram: Machine resumed.");
+
+        let machine = &ctx.accounts.breeding_machine;
+        emit!(MachineResumed {
+            machine: machine.key(),
+            authority: machine.authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn propose_authority(ctx: Context<ProposeAuthority>, new_authority: Pubkey) -> Result<()> {
+        ctx.accounts.breeding_machine.pending_authority = Some(new_authority);
+
+        msg!("BreedingProgram: Authority transfer proposed.");
+
+        let machine = &ctx.accounts.breeding_machine;
+        emit!(AuthorityProposed {
+            machine: machine.key(),
+            authority: machine.authority,
+            pending_authority: new_authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn accept_authority(ctx: Context<AcceptAuthority>) -> Result<()> {
+        let machine = &mut ctx.accounts.breeding_machine;
+        let previous_authority = machine.authority;
+        machine.authority = ctx.accounts.new_authority.key();
+        machine.pending_authority = None;
+
+        msg!("BreedingProgram: Authority transfer accepted.");
+
+        let machine = &ctx.accounts.breeding_machine;
+        emit!(AuthorityAccepted {
+            machine: machine.key(),
+            previous_authority,
+            authority: machine.authority,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    pub fn set_parent_collections(
+        ctx: Context<UpdateMachineConfig>,
+        extra_parent_collections: Vec<ParentCollection>,
+        compatibility: Option<[u8; MAX_PARENT_COLLECTIONS]>,
+    ) -> Result<()> {
+        require_gt!(
+            MAX_PARENT_COLLECTIONS,
+            extra_parent_collections.len(),
+            BreedingError::TooManyParentCollections
+        );
+
+        let machine = &mut ctx.accounts.breeding_machine;
+        machine.extra_parent_collections = extra_parent_collections;
+        machine.compatibility = compatibility;
+
+        let machine = &ctx.accounts.breeding_machine;
+        emit!(ParentCollectionsUpdated {
+            machine: machine.key(),
+            authority: machine.authority,
+            extra_parent_collections: machine.extra_parent_collections.clone(),
+            compatibility: machine.compatibility,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Makes finalize mint the child NFT instead of sending a whitelist token.
+    /// Unset to go back to whitelist tokens.
+    pub fn set_child_nft(
+        ctx: Context<UpdateMachineConfig>,
+        child_nft: Option<ChildNft>,
+    ) -> Result<()> {
+        if let Some(child_nft) = &child_nft {
+            child_nft.validate()?;
+        }
+
+        ctx.accounts.breeding_machine.child_nft = child_nft;
+
+        let machine = &ctx.accounts.breeding_machine;
+        emit!(ChildNftUpdated {
+            machine: machine.key(),
+            authority: machine.authority,
+            child_nft: machine.child_nft.clone(),
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Adds a reward tier with its own whitelist token, minting its whole
+    /// supply like `create_machine`. Tiers are numbered from 1, after the
+    /// config whitelist token.
+    pub fn add_reward_tier(
+        ctx: Context<AddRewardTier>,
+        tier: u8,
+        weight: u16,
+        supply: u64,
+    ) -> Result<()> {
+        let machine = &ctx.accounts.breeding_machine;
+        require_eq!(
+            tier as usize,
+            machine.extra_reward_tiers.len() + 1,
+            BreedingError::InvalidRewardTier
+        );
+        require_gt!(
+            MAX_REWARD_TIERS,
+            machine.extra_reward_tiers.len() + 1,
+            BreedingError::InvalidRewardTier
+        );
+
+        let machine_seeds = machine.seeds();
+
+        anchor_spl::token::mint_to(
+            ctx.accounts.mint_to_ctx().with_signer(&[&machine_seeds]),
+            supply,
+        )?;
+
+        anchor_spl::token::set_authority(
+            ctx.accounts
+                .set_authority_ctx()
+                .with_signer(&[&machine_seeds]),
+            AuthorityType::MintTokens,
+            None,
+        )?;
+
+        let bump = *ctx.bumps.get("tier_token").unwrap();
+        ctx.accounts
+            .breeding_machine
+            .extra_reward_tiers
+            .push(RewardTier { weight, bump });
+
+        let machine = &ctx.accounts.breeding_machine;
+        emit!(RewardTierAdded {
+            machine: machine.key(),
+            authority: machine.authority,
+            tier,
+            weight,
+            supply,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Sets the weight of a reward tier, 0 being the config whitelist token.
+    pub fn set_reward_tier_weight(
+        ctx: Context<UpdateMachineConfig>,
+        tier: u8,
+        weight: u16,
+    ) -> Result<()> {
+        let machine = &mut ctx.accounts.breeding_machine;
+
+        match tier {
+            0 => machine.base_reward_weight = weight,
+            _ => {
+                machine
+                    .extra_reward_tiers
+                    .get_mut(tier as usize - 1)
+                    .ok_or(BreedingError::InvalidRewardTier)?
+                    .weight = weight
+            }
+        }
+
+        let machine = &ctx.accounts.breeding_machine;
+        emit!(RewardTierWeightUpdated {
+            machine: machine.key(),
+            authority: machine.authority,
+            tier,
+            weight,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Mints more whitelist tokens to the vault of a `mint_capped` machine, up
+    /// to its `reward_cap`.
+    pub fn replenish_rewards(ctx: Context<ReplenishRewards>, amount: u64) -> Result<()> {
+        let config = &ctx.accounts.breeding_machine.config;
+        require!(config.mint_capped, BreedingError::NotMintCapped);
+
+        let reward_supply = config
+            .reward_supply
+            .checked_add(amount)
+            .ok_or(BreedingError::ArithmeticError)?;
+        require_gte!(
+            config.reward_cap,
+            reward_supply,
+            BreedingError::RewardCapExceeded
+        );
+
+        let machine_seeds = ctx.accounts.breeding_machine.seeds();
+        anchor_spl::token::mint_to(
+            ctx.accounts.mint_to_ctx().with_signer(&[&machine_seeds]),
+            amount,
+        )?;
+
+        ctx.accounts.breeding_machine.config.reward_supply = reward_supply;
+
+        let machine = &ctx.accounts.breeding_machine;
+        emit!(RewardsReplenished {
+            machine: machine.key(),
+            authority: machine.authority,
+            amount,
+            reward_supply,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Stores the outcome seed of a breeding while its reveal slot is in the
+    /// `SlotHashes` sysvar, so it can be finalized later. Anyone can call it,
+    /// the seed doesn't depend on who reveals it or when.
+    pub fn reveal_breeding(ctx: Context<RevealBreeding>) -> Result<()> {
+        let breed_data = &mut ctx.accounts.breed_data;
+
+        if breed_data.seed.is_some() {
+            return Ok(());
+        }
+
+        let seed = randomness::outcome_seed(
+            &breed_data.commitment,
+            breed_data.commit_slot,
+            &ctx.accounts.slot_hashes,
+        )?
+        .ok_or(BreedingError::RevealExpired)?;
+        breed_data.seed = Some(seed);
+
+        emit!(BreedingRevealed {
+            breed_data: breed_data.key(),
+            owner: breed_data.owner,
+            seed,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Moves a machine created before the PDA seeds stopped depending on the
+    /// authority to the current account layout.
+    pub fn migrate_machine(ctx: Context<MigrateMachine>) -> Result<()> {
+        let machine = ctx.accounts.migrate()?;
+
+        emit!(MachineMigrated {
+            machine: ctx.accounts.breeding_machine.key(),
+            authority: machine.authority,
+            bred: machine.bred,
+            born: machine.born,
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
+
+    /// Finalizes a breeding started before its machine was migrated with the
+    /// legacy rules: once the machine breeding time elapsed, the parents are
+    /// returned or burned and the owner gets a config whitelist token.
+    pub fn finalize_legacy_breeding(ctx: Context<FinalizeLegacyBreeding>) -> Result<()> {
+        let lock = ctx.accounts.legacy_lock();
+        let (legacy, bump) = lock.load()?;
+
+        let config = ctx.accounts.breeding_machine.config;
+        let now = Clock::get()?.unix_timestamp;
+        let breeding_time = i64::try_from(config.breeding_time).unwrap_or(i64::MAX);
+        require_gte!(
+            now,
+            legacy.timestamp.saturating_add(breeding_time),
+            BreedingError::StillInProgress
+        );
+
+        // Legacy breedings didn't reserve their whitelist token, so they can't
+        // take one reserved by a breeding in progress.
+        require_gt!(
+            ctx.accounts.whitelist_vault.amount,
+            ctx.accounts.breeding_machine.reserved,
+            BreedingError::RewardsSoldOut
+        );
+
+        lock.release(&legacy, bump, config.burn_parents)?;
+
+        let machine_seeds = ctx.accounts.breeding_machine.seeds();
+        anchor_spl::token::transfer(
+            ctx.accounts.transfer_ctx().with_signer(&[&machine_seeds]),
+            1,
+        )?;
+
+        let machine = &mut ctx.accounts.breeding_machine;
+        machine.bred = machine
+            .bred
+            .checked_add(2)
+            .ok_or(BreedingError::ArithmeticError)?;
+        machine.born = machine
+            .born
+            .checked_add(1)
+            .ok_or(BreedingError::ArithmeticError)?;
+
+        emit!(LegacyBreedingFinalized {
+            machine: machine.key(),
+            breed_data: ctx.accounts.breed_data.key(),
+            owner: legacy.owner,
+            parents: vec![legacy.mint_a, legacy.mint_b],
+            parents_burned: config.burn_parents,
+            bred: machine.bred,
+            born: machine.born,
+            timestamp: now,
+        });
 
         Ok(())
     }
 
-    // TODO: close breeding machine
+    /// Returns the parents of a breeding started before its machine was
+    /// migrated, without a reward. Their fee was never escrowed, so nothing is
+    /// refunded. Works even once the machine is closed, unlike
+    /// `finalize_legacy_breeding`.
+    pub fn cancel_legacy_breeding(ctx: Context<CancelLegacyBreeding>) -> Result<()> {
+        let lock = ctx.accounts.legacy_lock();
+        let (legacy, bump) = lock.load()?;
+
+        lock.release(&legacy, bump, false)?;
+
+        emit!(LegacyBreedingCancelled {
+            machine: ctx.accounts.breeding_machine.key(),
+            breed_data: ctx.accounts.breed_data.key(),
+            owner: legacy.owner,
+            parents: vec![legacy.mint_a, legacy.mint_b],
+            timestamp: Clock::get()?.unix_timestamp,
+        });
+
+        Ok(())
+    }
 }
 
 #[account]
 pub struct BreedMachine {
     pub authority: Pubkey,
+    // Authority proposed by the current one, set until it accepts the transfer.
+    pub pending_authority: Option<Pubkey>,
+    // Wallet that created the machine. Used in the PDA seeds, never changes.
+    pub creator: Pubkey,
+    pub bump: u8,
     // How many NFTs were fed into the machine.
     pub bred: u64,
     // How many NFTs were generated.
     pub born: u64,
+    // How many whitelist tokens are reserved by breedings in progress.
+    pub reserved: u64,
+    // How many breedings are currently in progress.
+    pub in_progress: u64,
+    // New breedings can't be initialized while paused.
+    pub paused: bool,
+    // Collections allowed to breed besides the config one.
+    pub extra_parent_collections: Vec<ParentCollection>,
+    // Row `i` bit `j` allows parent collections `i` and `j` to breed together,
+    // indexed as in `BreedMachine::parent_collections`. Any pair can breed when unset.
+    pub compatibility: Option<[u8; MAX_PARENT_COLLECTIONS]>,
+    // Finalize mints the child NFT when set, and sends a whitelist token otherwise.
+    pub child_nft: Option<ChildNft>,
     pub config: BreedConfig,
+    // Weight of the config whitelist token among the reward tiers.
+    pub base_reward_weight: u16,
+    // Reward tiers besides the config whitelist token, e.g. rare and legendary.
+    pub extra_reward_tiers: Vec<RewardTier>,
 }
 
 impl BreedMachine {
     // Account discriminator byte not considered.
-    pub const LEN: usize = 32 + 8 + 8 + BreedConfig::LEN;
+    pub const LEN: usize = 32
+        + 33
+        + 32
+        + 1
+        + 8
+        + 8
+        + 8
+        + 8
+        + 1
+        + Self::PARENT_COLLECTIONS_LEN
+        + 1
+        + ChildNft::LEN
+        + BreedConfig::LEN
+        + 2
+        + 4
+        + (MAX_REWARD_TIERS - 1) * RewardTier::LEN;
+    // Extra parent collections and compatibility matrix.
+    const PARENT_COLLECTIONS_LEN: usize =
+        4 + (MAX_PARENT_COLLECTIONS - 1) * ParentCollection::LEN + 1 + MAX_PARENT_COLLECTIONS;
     pub const PREFIX: &'static [u8] = b"breed_machine";
 
-    pub fn new(authority: Pubkey, config: BreedConfig) -> Self {
+    pub fn new(authority: Pubkey, bump: u8, config: BreedConfig) -> Self {
         Self {
             authority,
+            pending_authority: None,
+            creator: authority,
+            bump,
             bred: 0,
             born: 0,
+            reserved: 0,
+            in_progress: 0,
+            paused: false,
+            extra_parent_collections: vec![],
+            compatibility: None,
+            child_nft: None,
             config,
+            base_reward_weight: 1,
+            extra_reward_tiers: vec![],
+        }
+    }
+
+    /// Releases a whitelist token reserved by a breeding.
+    pub fn release_reward(&mut self) -> Result<()> {
+        self.reserved = self
+            .reserved
+            .checked_sub(1)
+            .ok_or(BreedingError::ArithmeticError)?;
+
+        Ok(())
+    }
+
+    /// Weights of the reward tiers, the config whitelist token first.
+    pub fn reward_weights(&self) -> impl Iterator<Item = u16> + '_ {
+        std::iter::once(self.base_reward_weight)
+            .chain(self.extra_reward_tiers.iter().map(|tier| tier.weight))
+    }
+
+    /// The config parent collection first, then the extra ones.
+    pub fn parent_collections(&self) -> impl Iterator<Item = ParentCollection> + '_ {
+        let config_collection = ParentCollection {
+            creator: self.config.parents_candy_machine,
+            collection: self.config.parents_collection,
+        };
+
+        std::iter::once(config_collection).chain(self.extra_parent_collections.iter().copied())
+    }
+
+    /// Takes the bitmasks of the parent collections each parent belongs to.
+    pub fn can_breed_together(&self, parent_a: u8, parent_b: u8) -> bool {
+        let rows = match self.compatibility {
+            Some(rows) => rows,
+            None => return true,
+        };
+
+        // The matrix is read both ways, so it doesn't need to be symmetric.
+        rows.iter().enumerate().any(|(i, row)| {
+            (parent_a & 1 << i != 0 && row & parent_b != 0)
+                || (parent_b & 1 << i != 0 && row & parent_a != 0)
+        })
+    }
+
+    pub fn seeds(&self) -> [&[u8]; 5] {
+        [
+            Self::PREFIX,
+            self.config.parents_candy_machine.as_ref(),
+            self.config.reward_candy_machine.as_ref(),
+            self.creator.as_ref(),
+            std::slice::from_ref(&self.bump), // must come last
+        ]
+    }
+}
+
+/// Machine layout from before the PDA seeds stopped depending on the authority.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyBreedMachine {
+    pub authority: Pubkey,
+    pub bred: u64,
+    pub born: u64,
+    pub config: LegacyBreedConfig,
+}
+
+impl LegacyBreedMachine {
+    // Account discriminator byte not considered.
+    pub const LEN: usize = 32 + 8 + 8 + LegacyBreedConfig::LEN;
+}
+
+/// Breed data layout from before the machine migration, keyed by both parent
+/// mints. Only read to cancel breedings started before the migration.
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyBreedData {
+    pub owner: Pubkey,
+    pub authority: Pubkey,
+    pub timestamp: i64,
+    pub mint_a: Pubkey,
+    pub mint_b: Pubkey,
+}
+
+impl LegacyBreedData {
+    // Account discriminator byte not considered.
+    pub const LEN: usize = 8 + 32 + 32 + 32 + 32;
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LegacyBreedConfig {
+    pub breeding_time: u64,
+    pub burn_parents: bool,
+    pub parents_candy_machine: Pubkey,
+    pub reward_candy_machine: Pubkey,
+    pub initialization_fee_token: Pubkey,
+    pub initialization_fee_price: u64,
+    pub reward_supply: u64,
+}
+
+impl LegacyBreedConfig {
+    pub const LEN: usize = 8 + 1 + 32 + 32 + 32 + 8 + 8;
+}
+
+impl From<LegacyBreedConfig> for BreedConfig {
+    fn from(legacy: LegacyBreedConfig) -> Self {
+        Self {
+            breeding_time: legacy.breeding_time,
+            burn_parents: legacy.burn_parents,
+            parents_candy_machine: legacy.parents_candy_machine,
+            parents_collection: Pubkey::default(),
+            parent_validation: ParentValidation::VerifiedCreator,
+            parents_count: 2,
+            reward_candy_machine: legacy.reward_candy_machine,
+            initialization_fee_token: legacy.initialization_fee_token,
+            initialization_fee_price: legacy.initialization_fee_price,
+            fee_mode: FeeMode::Burn,
+            fee_treasury: Pubkey::default(),
+            sol_fee_lamports: 0,
+            sol_fee_treasury: Pubkey::default(),
+            cancel_policy: CancelPolicy::FullRefund,
+            reward_supply: legacy.reward_supply,
+            breeding_cooldown: 0,
+            max_breeds_per_parent: 0,
+            lock_mode: LockMode::Vault,
+            success_bps: MAX_BPS,
+            mint_capped: false,
+            reward_cap: 0,
         }
     }
 }
@@ -191,47 +1022,485 @@ pub struct BreedConfig {
     pub burn_parents: bool,
     // Candy machine address in parents NFTs
     pub parents_candy_machine: Pubkey,
+    // Metaplex collection of the parents NFTs.
+    pub parents_collection: Pubkey,
+    // How parents are checked to belong to this machine.
+    pub parent_validation: ParentValidation,
+    // How many parents a breeding takes, up to `MAX_PARENTS`.
+    pub parents_count: u8,
     // Candy machine address in children NFTs.
     pub reward_candy_machine: Pubkey,
     // Mint address for the token charged on breeding initialization.
     pub initialization_fee_token: Pubkey,
     // How much to charge on breeding initialization.
     pub initialization_fee_price: u64,
-    // How many NFTs can be minted after the breeding.
+    // What to do with the initialization fee.
+    pub fee_mode: FeeMode,
+    // Token account receiving the fee, for the fee modes that use it.
+    pub fee_treasury: Pubkey,
+    // Lamports charged on breeding initialization, on top of the token fee.
+    pub sol_fee_lamports: u64,
+    // Wallet receiving the SOL fee.
+    pub sol_fee_treasury: Pubkey,
+    // How much of the fees is refunded when a breeding is cancelled.
+    pub cancel_policy: CancelPolicy,
+    // How many NFTs can be minted after the breeding. Grows when a
+    // `mint_capped` machine replenishes its rewards.
     pub reward_supply: u64,
+    // How long (seconds) a parent must wait after a breeding to breed again,
+    // counted from its last breeding in any machine.
+    pub breeding_cooldown: u64,
+    // How many times a parent can breed, counting its breedings in every
+    // machine. Zero means unlimited.
+    pub max_breeds_per_parent: u64,
+    // How parents are locked while breeding.
+    pub lock_mode: LockMode,
+    // Odds of a breeding to give a child, in basis points. Below `MAX_BPS`,
+    // the outcome is rolled on finalize, see `randomness`.
+    pub success_bps: u16,
+    // Whether the machine keeps the whitelist mint authority to replenish the
+    // rewards. Fixed at creation.
+    pub mint_capped: bool,
+    // Hard cap of `reward_supply` for `mint_capped` machines. Fixed at
+    // creation.
+    pub reward_cap: u64,
 }
 
 impl BreedConfig {
-    pub const LEN: usize = 8 + 1 + 32 + 32 + 32 + 8 + 8;
+    pub const LEN: usize = 8
+        + 1
+        + 32
+        + 32
+        + 1
+        + 1
+        + 32
+        + 32
+        + 8
+        + FeeMode::LEN
+        + 32
+        + 8
+        + 32
+        + CancelPolicy::LEN
+        + 8
+        + 8
+        + 8
+        + LockMode::LEN
+        + 2
+        + 1
+        + 8;
+
+    pub fn validate(&self) -> Result<()> {
+        require!(
+            (1..=MAX_PARENTS).contains(&(self.parents_count as usize)),
+            BreedingError::InvalidParentCount
+        );
+
+        if let FeeMode::Split { burn_bps } = self.fee_mode {
+            require_gte!(MAX_BPS, burn_bps, BreedingError::InvalidBasisPoints);
+        }
+
+        require_gte!(MAX_BPS, self.success_bps, BreedingError::InvalidBasisPoints);
+
+        if self.mint_capped {
+            require_gte!(
+                self.reward_cap,
+                self.reward_supply,
+                BreedingError::RewardCapExceeded
+            );
+        }
+
+        match self.cancel_policy {
+            CancelPolicy::FullRefund => {}
+            CancelPolicy::PartialRefund { refund_bps: bps }
+            | CancelPolicy::TimePenalty {
+                max_penalty_bps: bps,
+            } => require_gte!(MAX_BPS, bps, BreedingError::InvalidBasisPoints),
+        }
+
+        let treasury_share = match self.fee_mode {
+            FeeMode::Burn => false,
+            FeeMode::Treasury => true,
+            FeeMode::Split { burn_bps } => burn_bps < MAX_BPS,
+        };
+        if treasury_share && self.initialization_fee_price > 0 {
+            require_keys_neq!(
+                self.fee_treasury,
+                Pubkey::default(),
+                BreedingError::InvalidFeeTreasury
+            );
+        }
+
+        if self.sol_fee_lamports > 0 {
+            require_keys_neq!(
+                self.sol_fee_treasury,
+                Pubkey::default(),
+                BreedingError::InvalidFeeTreasury
+            );
+        }
+
+        Ok(())
+    }
+}
+
+pub const MAX_BPS: u16 = 10_000;
+
+#[derive(Debug, Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize)]
+pub enum FeeMode {
+    // Burn the whole fee.
+    Burn,
+    // Send the whole fee to the treasury.
+    Treasury,
+    // Burn `burn_bps` basis points of the fee and send the rest to the treasury.
+    Split { burn_bps: u16 },
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize)]
+pub enum CancelPolicy {
+    // Refund the whole fee.
+    FullRefund,
+    // Refund `refund_bps` basis points of the fee.
+    PartialRefund { refund_bps: u16 },
+    // Keep a penalty growing linearly with the elapsed time, up to
+    // `max_penalty_bps` basis points once the breeding time is over.
+    TimePenalty { max_penalty_bps: u16 },
+}
+
+impl CancelPolicy {
+    pub const LEN: usize = 1 + 2;
+
+    /// Returns how much of the fee is refunded when cancelling after `elapsed` seconds.
+    pub fn refund(&self, fee: u64, elapsed: u64, breeding_time: u64) -> u64 {
+        let penalty_bps = match *self {
+            CancelPolicy::FullRefund => 0,
+            CancelPolicy::PartialRefund { refund_bps } => {
+                MAX_BPS.saturating_sub(refund_bps) as u128
+            }
+            CancelPolicy::TimePenalty { max_penalty_bps } if elapsed < breeding_time => {
+                max_penalty_bps as u128 * elapsed as u128 / breeding_time as u128
+            }
+            CancelPolicy::TimePenalty { max_penalty_bps } => max_penalty_bps as u128,
+        };
+
+        let penalty = fee as u128 * penalty_bps / MAX_BPS as u128;
+        fee - (penalty as u64).min(fee)
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize)]
+pub enum LockMode {
+    // Move the parents to breed data ATAs.
+    Vault,
+    // Approve breed data as delegate and freeze the parents in the user ATAs,
+    // so they stay in the user wallet. Parents must have a master edition.
+    Freeze,
+}
+
+impl LockMode {
+    pub const LEN: usize = 1;
+}
+
+impl FeeMode {
+    pub const LEN: usize = 1 + 2;
+
+    /// Returns how much of the fee is burned and how much goes to the treasury.
+    pub fn split(&self, amount: u64) -> (u64, u64) {
+        let burn = match *self {
+            FeeMode::Burn => amount,
+            FeeMode::Treasury => 0,
+            FeeMode::Split { burn_bps } => {
+                let burn = amount as u128 * burn_bps as u128 / MAX_BPS as u128;
+                (burn as u64).min(amount)
+            }
+        };
+
+        (burn, amount - burn)
+    }
+}
+
+pub const MAX_PARENT_COLLECTIONS: usize = 8;
+
+pub const MAX_PARENTS: usize = 4;
+
+// Common, rare and legendary.
+pub const MAX_REWARD_TIERS: usize = 3;
+
+/// Whitelist token of a reward tier besides the config one. Its mint is at
+/// `[b"whitelist_token", machine, tier]`, tier 0 being the config one.
+#[derive(Debug, Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize)]
+pub struct RewardTier {
+    // Relative odds among the tiers with whitelist tokens left.
+    pub weight: u16,
+    // Bump of the mint PDA.
+    pub bump: u8,
+}
+
+impl RewardTier {
+    pub const LEN: usize = 2 + 1;
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize)]
+pub struct ParentCollection {
+    // Verified creator in the parents NFTs.
+    pub creator: Pubkey,
+    // Metaplex collection of the parents NFTs.
+    pub collection: Pubkey,
+}
+
+impl ParentCollection {
+    pub const LEN: usize = 32 + 32;
+}
+
+/// Metadata of the NFTs minted on finalize.
+#[derive(Debug, Clone, PartialEq, AnchorSerialize, AnchorDeserialize)]
+pub struct ChildNft {
+    // Verified collection of the children. The machine must be its update authority.
+    pub collection: Pubkey,
+    // Children are named `{name} #{number}`.
+    pub name: String,
+    pub symbol: String,
+    // Children metadata is at `{uri_prefix}{number}.json`.
+    pub uri_prefix: String,
+    pub seller_fee_basis_points: u16,
+}
+
+impl ChildNft {
+    // Leaves room in the metadata name and URI for the child number.
+    pub const MAX_NAME_LEN: usize = 24;
+    pub const MAX_SYMBOL_LEN: usize = 10;
+    // `{uri_prefix}{number}.json` fits the metadata URI for any `u64` number.
+    pub const MAX_URI_PREFIX_LEN: usize =
+        mpl_token_metadata::state::MAX_URI_LENGTH - Self::MAX_NUMBER_LEN - ".json".len();
+    // Digits of `u64::MAX`.
+    const MAX_NUMBER_LEN: usize = 20;
+    pub const LEN: usize =
+        32 + 4 + Self::MAX_NAME_LEN + 4 + Self::MAX_SYMBOL_LEN + 4 + Self::MAX_URI_PREFIX_LEN + 2;
+
+    pub fn validate(&self) -> Result<()> {
+        require_gte!(
+            Self::MAX_NAME_LEN,
+            self.name.len(),
+            BreedingError::InvalidChildNft
+        );
+        require_gte!(
+            Self::MAX_SYMBOL_LEN,
+            self.symbol.len(),
+            BreedingError::InvalidChildNft
+        );
+        require_gte!(
+            Self::MAX_URI_PREFIX_LEN,
+            self.uri_prefix.len(),
+            BreedingError::InvalidChildNft
+        );
+        require_gte!(
+            MAX_BPS,
+            self.seller_fee_basis_points,
+            BreedingError::InvalidBasisPoints
+        );
+
+        Ok(())
+    }
+
+    /// Metadata name and URI of the child born `number`th in the machine.
+    pub fn metadata(&self, number: u64) -> (String, String) {
+        (
+            format!("{} #{}", self.name, number),
+            format!("{}{}.json", self.uri_prefix, number),
+        )
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, AnchorSerialize, AnchorDeserialize)]
+pub enum ParentValidation {
+    // `parents_candy_machine` must be a verified creator of the parents.
+    VerifiedCreator,
+    // Parents must be verified items of `parents_collection`.
+    VerifiedCollection,
+    // Parents must pass both checks.
+    Both,
 }
 
 /// This account will manage a user's breeding progress, locking the NFTs in the meantime.
 /// The NFTs would only be burned once the breeding is complete.
+///
+/// Depending on the lock mode, it holds the parents in its ATAs or is their
+/// delegate while they're frozen in the user ATAs.
 #[account]
 pub struct BreedData {
     pub owner: Pubkey,
     pub authority: Pubkey,
     pub timestamp: i64,
-    pub mint_a: Pubkey,
-    pub mint_b: Pubkey,
+    // Parent mints, sorted.
+    pub mints: Vec<Pubkey>,
+    // Machine config at the time the breeding started, so later config
+    // updates don't change the outcome of breedings already in progress.
+    pub breeding_time: u64,
+    pub burn_parents: bool,
+    pub fee_token: Pubkey,
+    pub fee_paid: u64,
+    pub sol_fee_paid: u64,
+    pub fee_mode: FeeMode,
+    pub fee_treasury: Pubkey,
+    pub sol_fee_treasury: Pubkey,
+    pub cancel_policy: CancelPolicy,
+    pub lock_mode: LockMode,
+    pub success_bps: u16,
+    // Outcome commitment and the slot it was made in, see `randomness`.
+    pub commitment: [u8; 32],
+    pub commit_slot: u64,
+    // Outcome seed, stored by `reveal_breeding`.
+    pub seed: Option<[u8; 32]>,
+    // Whether the outcome is rolled, fixed when the breeding started: it can
+    // fail, or picks a whitelist token among several weighted tiers.
+    pub rolls: bool,
+    // Whether a whitelist token is reserved for this breeding, released on
+    // finalize or cancel.
+    pub reserved_reward: bool,
 }
 
 impl BreedData {
     // Account discriminator byte not considered.
-    pub const LEN: usize = 8 + 32 + 32 + 32 + 32;
+    pub const LEN: usize = 8
+        + 32
+        + 32
+        + 4
+        + MAX_PARENTS * 32
+        + 8
+        + 1
+        + 32
+        + 8
+        + 8
+        + FeeMode::LEN
+        + 32
+        + 32
+        + CancelPolicy::LEN
+        + LockMode::LEN
+        + 2
+        + 32
+        + 8
+        + 1
+        + 32
+        + 1
+        + 1;
     pub const PREFIX: &'static [u8] = b"breed_account";
 
-    pub fn new(authority: Pubkey, owner: Pubkey, mint_a: Pubkey, mint_b: Pubkey) -> Result<Self> {
+    /// Mint in the breed data seeds. A parent can only be in one breeding at a
+    /// time, so the first one is enough to tell breedings apart.
+    pub fn seed_mint(mints: &[Pubkey]) -> Pubkey {
+        mints.first().copied().unwrap_or_default()
+    }
+
+    pub fn new(
+        address: &Pubkey,
+        authority: Pubkey,
+        owner: Pubkey,
+        mints: Vec<Pubkey>,
+        config: &BreedConfig,
+    ) -> Result<Self> {
+        let clock = Clock::get()?;
+
         Ok(Self {
             authority,
             owner,
-            timestamp: Clock::get()?.unix_timestamp,
-            mint_a,
-            mint_b,
+            timestamp: clock.unix_timestamp,
+            mints,
+            breeding_time: config.breeding_time,
+            burn_parents: config.burn_parents,
+            fee_token: config.initialization_fee_token,
+            fee_paid: config.initialization_fee_price,
+            sol_fee_paid: config.sol_fee_lamports,
+            fee_mode: config.fee_mode,
+            fee_treasury: config.fee_treasury,
+            sol_fee_treasury: config.sol_fee_treasury,
+            cancel_policy: config.cancel_policy,
+            lock_mode: config.lock_mode,
+            success_bps: config.success_bps,
+            commitment: randomness::commitment(address, clock.slot, clock.unix_timestamp),
+            commit_slot: clock.slot,
+            seed: None,
+            rolls: false,
+            reserved_reward: false,
         })
     }
 }
 
+/// Tracks a parent NFT breeding history across all the machines. It's keyed by
+/// the mint only, so the cooldown and breed limit of a machine also count the
+/// breedings the parent finalized in other machines.
+#[account]
+pub struct ParentRecord {
+    pub mint: Pubkey,
+    // How many breedings this NFT has finalized.
+    pub times_bred: u64,
+    // When its last breeding was finalized.
+    pub last_bred_at: i64,
+    // Zero for NFTs that weren't born from a breeding.
+    pub generation: u16,
+    pub bump: u8,
+}
+
+impl ParentRecord {
+    // Account discriminator byte not considered.
+    pub const LEN: usize = 32 + 8 + 8 + 2 + 1;
+    pub const PREFIX: &'static [u8] = b"parent_record";
+
+    pub fn can_breed(&self, config: &BreedConfig, now: i64) -> Result<()> {
+        if config.max_breeds_per_parent > 0 {
+            require_gt!(
+                config.max_breeds_per_parent,
+                self.times_bred,
+                BreedingError::MaxBreedsReached
+            );
+        }
+
+        // Parents that never bred have no cooldown. Cooldowns too long to fit
+        // the clock never end.
+        if self.times_bred > 0 {
+            let cooldown_end = i64::try_from(config.breeding_cooldown)
+                .ok()
+                .and_then(|cooldown| self.last_bred_at.checked_add(cooldown))
+                .unwrap_or(i64::MAX);
+
+            require_gte!(now, cooldown_end, BreedingError::ParentInCooldown);
+        }
+
+        Ok(())
+    }
+
+    pub fn record_breed(&mut self, now: i64) -> Result<()> {
+        self.times_bred = self
+            .times_bred
+            .checked_add(1)
+            .ok_or(BreedingError::ArithmeticError)?;
+        self.last_bred_at = now;
+
+        Ok(())
+    }
+}
+
+/// Links a breeding child to its parents.
+///
+/// Keyed by the child mint when the machine mints the child NFT, or by the
+/// machine and breed id when it sends a reward whitelist token instead.
+#[account]
+pub struct Lineage {
+    pub machine: Pubkey,
+    // The machine `born` counter when the breeding was finalized.
+    pub breed_id: u64,
+    // None when the child is minted later from a reward whitelist token.
+    pub child_mint: Option<Pubkey>,
+    pub parents: Vec<Pubkey>,
+    // Max generation of the parents plus one.
+    pub generation: u16,
+    pub timestamp: i64,
+    pub bump: u8,
+}
+
+impl Lineage {
+    // Account discriminator byte not considered.
+    pub const LEN: usize = 32 + 8 + 33 + 4 + MAX_PARENTS * 32 + 2 + 8 + 1;
+    pub const PREFIX: &'static [u8] = b"lineage";
+}
+
 #[error_code]
 pub enum BreedingError {
     #[msg("Breeding is still in progress.")]
@@ -240,4 +1509,50 @@ pub enum BreedingError {
     ArithmeticError,
     #[msg("Invalid NFT collection to breed in this machine.")]
     InvalidNftCollection,
+    #[msg("There are breedings in progress in this machine.")]
+    ActiveBreedings,
+    #[msg("Only the breeding owner can finalize or cancel it.")]
+    InvalidBreedOwner,
+    #[msg("This machine is paused.")]
+    MachinePaused,
+    #[msg("Signer is not the pending authority of this machine.")]
+    InvalidPendingAuthority,
+    #[msg("Machine account is already up to date.")]
+    AlreadyMigrated,
+    #[msg("Parent is still in its breeding cooldown.")]
+    ParentInCooldown,
+    #[msg("Parent reached the maximum number of breedings.")]
+    MaxBreedsReached,
+    #[msg("An NFT can't breed with itself.")]
+    SelfBreeding,
+    #[msg("Parents must be sorted by mint address.")]
+    UnsortedParents,
+    #[msg("Too many parent collections for a single machine.")]
+    TooManyParentCollections,
+    #[msg("These parent collections can't breed together.")]
+    IncompatibleParents,
+    #[msg("Basis points can't be over 10000.")]
+    InvalidBasisPoints,
+    #[msg("Fee treasury doesn't match the machine config.")]
+    InvalidFeeTreasury,
+    #[msg("Wrong number of parents for this machine.")]
+    InvalidParentCount,
+    #[msg("Child NFT name, symbol or URI is too long.")]
+    InvalidChildNft,
+    #[msg("Collection doesn't match the child NFT config.")]
+    InvalidRewardCollection,
+    #[msg("Breeding outcome can't be revealed before its reveal slot.")]
+    RevealTooEarly,
+    #[msg("Reward tier doesn't exist or the machine has too many.")]
+    InvalidRewardTier,
+    #[msg("Machine doesn't keep the whitelist mint authority.")]
+    NotMintCapped,
+    #[msg("Reward supply would exceed the machine reward cap.")]
+    RewardCapExceeded,
+    #[msg("Every whitelist token left is reserved by a breeding in progress.")]
+    RewardsSoldOut,
+    #[msg("Breeding reveal slot left the SlotHashes sysvar before it was revealed.")]
+    RevealExpired,
+    #[msg("Breedings with a rolled outcome can't be cancelled after their reveal slot.")]
+    OutcomeRevealed,
 }
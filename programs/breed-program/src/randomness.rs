@@ -0,0 +1,136 @@
+//! Commit-reveal randomness for the breeding outcomes.
+//!
+//! `initialize_breeding` stores a commitment of the breeding in the breed
+//! data, and the outcome mixes it with the hash of its reveal slot, the slot
+//! `REVEAL_DELAY` slots after the one it started in. That hash is unknown when
+//! the commitment is made, so users can't pick a favourable slot to start
+//! breeding in, and fixed once known, so they can't pick a favourable slot to
+//! finalize in either.
+//!
+//! `SlotHashes` only keeps the last 512 slots, so `reveal_breeding` stores the
+//! seed in the breed data for breedings finalized later than that. Breedings
+//! nobody revealed in time fail, so letting the reveal slot expire never beats
+//! the roll, and they can't be cancelled once it's produced either.
+
+use crate::{BreedingError, MAX_BPS};
+use anchor_lang::{
+    prelude::*,
+    solana_program::{hash::hashv, slot_hashes::MAX_ENTRIES},
+};
+
+/// Slots between the one a breeding starts in and the one whose hash reveals
+/// its outcome.
+pub const REVEAL_DELAY: u64 = 1;
+
+/// Commitment of a breeding, made when it starts.
+pub fn commitment(breed_data: &Pubkey, slot: u64, timestamp: i64) -> [u8; 32] {
+    hashv(&[
+        breed_data.as_ref(),
+        &slot.to_le_bytes(),
+        &timestamp.to_le_bytes(),
+    ])
+    .to_bytes()
+}
+
+/// Seed of the outcome roll, mixing the commitment with a later slot hash.
+pub fn reveal(commitment: &[u8; 32], slot_hash: &[u8; 32]) -> [u8; 32] {
+    hashv(&[commitment, slot_hash]).to_bytes()
+}
+
+/// Rolls a number of basis points, from 0 to `MAX_BPS` excluded.
+pub fn roll_bps(seed: &[u8; 32]) -> u16 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&seed[..8]);
+
+    (u64::from_le_bytes(bytes) % MAX_BPS as u64) as u16
+}
+
+/// Whether a breeding with `success_bps` odds succeeds with this seed.
+pub fn roll_success(seed: &[u8; 32], success_bps: u16) -> bool {
+    roll_bps(seed) < success_bps
+}
+
+/// Picks an index with odds proportional to `weights`, None when they're all
+/// zero. Uses other bytes of the seed than `roll_bps`.
+pub fn pick_tier(seed: &[u8; 32], weights: &[u64]) -> Option<usize> {
+    let total: u64 = weights.iter().sum();
+    if total == 0 {
+        return None;
+    }
+
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&seed[8..16]);
+    let mut roll = u64::from_le_bytes(bytes) % total;
+
+    weights.iter().position(|weight| {
+        if roll < *weight {
+            return true;
+        }
+        roll -= weight;
+        false
+    })
+}
+
+/// Slot whose hash reveals the outcome of a breeding started in `commit_slot`.
+pub fn reveal_slot(commit_slot: u64) -> u64 {
+    commit_slot.saturating_add(REVEAL_DELAY)
+}
+
+/// Seed of the outcome rolls of a breeding, from the hash of its reveal slot.
+/// None once it may have left the sysvar.
+pub fn outcome_seed(
+    commitment: &[u8; 32],
+    commit_slot: u64,
+    slot_hashes: &AccountInfo,
+) -> Result<Option<[u8; 32]>> {
+    let slot_hash = slot_hash_at(slot_hashes, reveal_slot(commit_slot))?;
+
+    Ok(slot_hash.map(|slot_hash| reveal(commitment, &slot_hash)))
+}
+
+/// Whether `slot` comes before the reveal slot of a breeding started in
+/// `commit_slot`, so its outcome can't be known yet.
+pub fn before_reveal(commit_slot: u64, slot: u64) -> bool {
+    slot < reveal_slot(commit_slot)
+}
+
+/// Reads the hash of the first slot at or after `slot` in the `SlotHashes`
+/// sysvar, reading its entries in place. Skipped slots have no hash,
+/// so the next produced slot takes their place.
+///
+/// Fails with `RevealTooEarly` until that slot is produced, and returns None
+/// once it may have left the sysvar.
+pub fn slot_hash_at(slot_hashes: &AccountInfo, slot: u64) -> Result<Option<[u8; 32]>> {
+    let data = slot_hashes.try_borrow_data()?;
+
+    // Entries count, then the entries from the most recent: slot and hash.
+    let mut count = [0; 8];
+    count.copy_from_slice(data.get(..8).ok_or(ErrorCode::AccountDidNotDeserialize)?);
+    let count = u64::from_le_bytes(count) as usize;
+
+    let mut found = None;
+    let mut reached_older = false;
+    for entry in data[8..].chunks_exact(8 + 32).take(count) {
+        let mut entry_slot = [0; 8];
+        entry_slot.copy_from_slice(&entry[..8]);
+        let entry_slot = u64::from_le_bytes(entry_slot);
+
+        if entry_slot < slot {
+            reached_older = true;
+            break;
+        }
+        found = Some((entry_slot, &entry[8..]));
+    }
+
+    let (found_slot, hash) = found.ok_or(BreedingError::RevealTooEarly)?;
+
+    // A full sysvar may have dropped an earlier slot at or after `slot`.
+    if !reached_older && found_slot != slot && count >= MAX_ENTRIES {
+        return Ok(None);
+    }
+
+    let mut slot_hash = [0; 32];
+    slot_hash.copy_from_slice(hash);
+
+    Ok(Some(slot_hash))
+}
@@ -0,0 +1,1148 @@
+//! Runs fully offline: `cargo test -p breed-program`.
+//!
+//! `BreedingError::ArithmeticError` isn't covered, the counters it guards
+//! can't overflow with valid accounts.
+
+mod support;
+
+use anchor_lang::{
+    error::ErrorCode, prelude::Pubkey, AnchorSerialize, Discriminator, InstructionData,
+};
+use anchor_spl::{associated_token::get_associated_token_address, token::spl_token};
+use breed_client::{accounts, instructions, pda};
+use breed_program::{
+    instructions::UpdateMachineConfigArgs, randomness, BreedConfig, BreedData, BreedMachine,
+    BreedingError, ChildNft, FeeMode, LegacyBreedData, Lineage, LockMode, ParentCollection,
+    ParentRecord, MAX_PARENT_COLLECTIONS,
+};
+use solana_program_test::ProgramTestContext;
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    program_pack::Pack,
+    signature::{Keypair, Signer},
+    transport::TransportError,
+};
+use support::*;
+
+struct Test {
+    context: ProgramTestContext,
+    authority: Keypair,
+    user: Keypair,
+    machine: Pubkey,
+    // Sorted parents of the machine collection, held by the user.
+    parents: Vec<Pubkey>,
+    // Held by the user, from a collection the machine doesn't know.
+    stranger: Pubkey,
+    // Held by the user, verified by `other_creator`.
+    other_parent: Pubkey,
+    other_creator: Pubkey,
+}
+
+impl Test {
+    /// Creates a machine with the default config changed by `configure`.
+    async fn new(configure: impl FnOnce(&mut BreedConfig)) -> Self {
+        let mut test = program_test();
+        let authority = add_wallet(&mut test);
+        let user = add_wallet(&mut test);
+
+        let creator = Pubkey::new_unique();
+        let other_creator = Pubkey::new_unique();
+        let parents = pda::sort_parents(&[
+            add_parent_nft(&mut test, &user.pubkey(), &creator),
+            add_parent_nft(&mut test, &user.pubkey(), &creator),
+        ]);
+        let stranger = add_parent_nft(&mut test, &user.pubkey(), &Pubkey::new_unique());
+        let other_parent = add_parent_nft(&mut test, &user.pubkey(), &other_creator);
+
+        let mut context = test.start_with_context().await;
+
+        let mut config = default_config(creator);
+        configure(&mut config);
+
+        let (machine, _) = pda::find_breeding_machine_address(
+            &config.parents_candy_machine,
+            &config.reward_candy_machine,
+            &authority.pubkey(),
+        );
+        let ix = instructions::create_machine(&authority.pubkey(), config);
+        process(&mut context, &[ix], &[&authority]).await.unwrap();
+
+        Self {
+            context,
+            authority,
+            user,
+            machine,
+            parents,
+            stranger,
+            other_parent,
+            other_creator,
+        }
+    }
+
+    async fn machine(&mut self) -> BreedMachine {
+        fetch(&mut self.context, &self.machine).await
+    }
+
+    fn breed_data_address(&self, parents: &[Pubkey]) -> Pubkey {
+        pda::find_breed_data_address(&self.machine, parents).0
+    }
+
+    async fn update(&mut self, args: UpdateMachineConfigArgs) -> Result<(), TransportError> {
+        let ix = instructions::update_machine_config(&self.machine, &self.authority.pubkey(), args);
+        process(&mut self.context, &[ix], &[&self.authority]).await
+    }
+
+    async fn init(&mut self, parents: &[Pubkey]) -> Result<(), TransportError> {
+        let machine = self.machine().await;
+        let ix = instructions::initialize_breeding(
+            &self.machine,
+            &machine,
+            &self.user.pubkey(),
+            parents,
+        );
+        process(&mut self.context, &[ix], &[&self.user]).await
+    }
+
+    /// Finalizes the breeding of `self.parents`.
+    async fn finalize(&mut self) -> Result<(), TransportError> {
+        let machine = self.machine().await;
+        let breed_data_address = self.breed_data_address(&self.parents);
+        let breed_data = fetch(&mut self.context, &breed_data_address).await;
+        let user = self.user.pubkey();
+
+        let mut ixs = vec![];
+
+        // The user whitelist ATA must exist when the machine sends whitelist tokens.
+        let whitelist_ata = self.whitelist_ata();
+        if machine.child_nft.is_none()
+            && token_balance(&mut self.context, &whitelist_ata)
+                .await
+                .is_none()
+        {
+            let whitelist_token = pda::find_whitelist_token_address(&self.machine).0;
+            ixs.push(instructions::create_associated_token_account(
+                &user,
+                &user,
+                &whitelist_token,
+            ));
+        }
+
+        ixs.push(instructions::finalize_breeding(
+            &self.machine,
+            &machine,
+            &breed_data,
+            &user,
+        ));
+        process(&mut self.context, &ixs, &[&self.user]).await
+    }
+
+    /// Cancels the breeding of `self.parents`.
+    async fn cancel(&mut self) -> Result<(), TransportError> {
+        let machine = self.machine().await;
+        let breed_data_address = self.breed_data_address(&self.parents);
+        let breed_data = fetch(&mut self.context, &breed_data_address).await;
+
+        let ix = instructions::cancel_breeding(
+            &self.machine,
+            &machine,
+            &breed_data,
+            &self.user.pubkey(),
+        );
+        process(&mut self.context, &[ix], &[&self.user]).await
+    }
+
+    fn whitelist_ata(&self) -> Pubkey {
+        get_associated_token_address(
+            &self.user.pubkey(),
+            &pda::find_whitelist_token_address(&self.machine).0,
+        )
+    }
+
+    async fn add_reward_tier(&mut self, weight: u16, supply: u64) -> Result<(), TransportError> {
+        let machine = self.machine().await;
+        let ix = instructions::add_reward_tier(
+            &self.machine,
+            &machine,
+            &self.authority.pubkey(),
+            weight,
+            supply,
+        );
+        process(&mut self.context, &[ix], &[&self.authority]).await
+    }
+
+    async fn set_reward_tier_weight(
+        &mut self,
+        tier: u8,
+        weight: u16,
+    ) -> Result<(), TransportError> {
+        let ix = instructions::set_reward_tier_weight(
+            &self.machine,
+            &self.authority.pubkey(),
+            tier,
+            weight,
+        );
+        process(&mut self.context, &[ix], &[&self.authority]).await
+    }
+
+    fn reward_tier_ata(&self, tier: u8) -> Pubkey {
+        get_associated_token_address(
+            &self.user.pubkey(),
+            &pda::find_reward_tier_token_address(&self.machine, tier).0,
+        )
+    }
+
+    /// Adds a breed data account of the user in the layout from before the
+    /// machine migration.
+    fn set_legacy_breed_data(&mut self, mint_a: Pubkey, mint_b: Pubkey) -> Pubkey {
+        let (address, _) = pda::find_legacy_breed_data_address(&self.machine, &mint_a, &mint_b);
+        let legacy = LegacyBreedData {
+            owner: self.user.pubkey(),
+            authority: self.authority.pubkey(),
+            timestamp: 0,
+            mint_a,
+            mint_b,
+        };
+
+        let mut data = BreedData::discriminator().to_vec();
+        data.extend(legacy.try_to_vec().unwrap());
+        self.context.set_account(
+            &address,
+            &AccountSharedData::from(Account {
+                lamports: SOL,
+                data,
+                owner: breed_program::ID,
+                ..Account::default()
+            }),
+        );
+
+        address
+    }
+
+    /// Locks `self.parents` the way legacy breedings did, in vault ATAs of a
+    /// legacy breed data. Returns the parents in their legacy order and the
+    /// breed data.
+    fn lock_legacy_parents(&mut self) -> (Pubkey, Pubkey, Pubkey) {
+        let user = self.user.pubkey();
+        let (mint_a, mint_b) = (self.parents[1], self.parents[0]);
+
+        let breed_data = self.set_legacy_breed_data(mint_a, mint_b);
+        for mint in [mint_a, mint_b].iter() {
+            for (owner, amount) in [(user, 0), (breed_data, 1)].iter() {
+                let mut token = vec![0; spl_token::state::Account::LEN];
+                spl_token::state::Account {
+                    mint: *mint,
+                    owner: *owner,
+                    amount: *amount,
+                    state: spl_token::state::AccountState::Initialized,
+                    ..spl_token::state::Account::default()
+                }
+                .pack_into_slice(&mut token);
+                self.context.set_account(
+                    &get_associated_token_address(owner, mint),
+                    &AccountSharedData::from(Account {
+                        lamports: SOL,
+                        data: token,
+                        owner: spl_token::ID,
+                        ..Account::default()
+                    }),
+                );
+            }
+        }
+
+        (mint_a, mint_b, breed_data)
+    }
+
+    async fn parent_balances(&mut self) -> Vec<Option<u64>> {
+        let mut balances = vec![];
+        for parent in self.parents.clone() {
+            let ata = get_associated_token_address(&self.user.pubkey(), &parent);
+            balances.push(token_balance(&mut self.context, &ata).await);
+        }
+
+        balances
+    }
+}
+
+#[tokio::test]
+async fn create_machine() {
+    let mut test = Test::new(|config| config.breeding_time = 100).await;
+
+    let machine = test.machine().await;
+    assert_eq!(machine.authority, test.authority.pubkey());
+    assert_eq!(machine.creator, test.authority.pubkey());
+    assert_eq!(machine.config.breeding_time, 100);
+    assert_eq!((machine.bred, machine.born, machine.in_progress), (0, 0, 0));
+
+    let vault = pda::whitelist_vault_address(&test.machine);
+    assert_eq!(token_balance(&mut test.context, &vault).await, Some(10));
+}
+
+#[tokio::test]
+async fn update_machine_config() {
+    let mut test = Test::new(|_| {}).await;
+
+    test.update(UpdateMachineConfigArgs {
+        breeding_time: Some(500),
+        burn_parents: Some(true),
+        ..UpdateMachineConfigArgs::default()
+    })
+    .await
+    .unwrap();
+
+    let config = test.machine().await.config;
+    assert_eq!(config.breeding_time, 500);
+    assert!(config.burn_parents);
+    assert_eq!(config.parents_count, 2);
+}
+
+#[tokio::test]
+async fn initialize_breeding_locks_parents() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = test.parents.clone();
+
+    test.init(&parents).await.unwrap();
+
+    let breed_data_address = test.breed_data_address(&parents);
+    let breed_data: BreedData = fetch(&mut test.context, &breed_data_address).await;
+    assert_eq!(breed_data.owner, test.user.pubkey());
+    assert_eq!(breed_data.mints, parents);
+
+    assert_eq!(test.parent_balances().await, vec![Some(0), Some(0)]);
+    for parent in &parents {
+        let vault = get_associated_token_address(&breed_data_address, parent);
+        assert_eq!(token_balance(&mut test.context, &vault).await, Some(1));
+    }
+
+    let machine = test.machine().await;
+    assert_eq!((machine.reserved, machine.in_progress), (1, 1));
+}
+
+#[tokio::test]
+async fn freeze_mode_takes_master_editions() {
+    // Freezing needs the token metadata program, which isn't loaded offline,
+    // so this only covers the accounts checked before the CPI.
+    let mut test = Test::new(|config| config.lock_mode = LockMode::Freeze).await;
+    let parents = test.parents.clone();
+
+    // Vault ATAs instead of master editions.
+    let mut machine = test.machine().await;
+    machine.config.lock_mode = LockMode::Vault;
+    let ix =
+        instructions::initialize_breeding(&test.machine, &machine, &test.user.pubkey(), &parents);
+
+    let result = process(&mut test.context, &[ix], &[&test.user]).await;
+    assert_error(result, ErrorCode::ConstraintSeeds);
+    assert_eq!(test.parent_balances().await, vec![Some(1), Some(1)]);
+}
+
+#[tokio::test]
+async fn finalize_breeding_returns_parents() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = test.parents.clone();
+
+    test.init(&parents).await.unwrap();
+    test.finalize().await.unwrap();
+
+    assert_eq!(test.parent_balances().await, vec![Some(1), Some(1)]);
+    let whitelist_ata = test.whitelist_ata();
+    assert_eq!(
+        token_balance(&mut test.context, &whitelist_ata).await,
+        Some(1)
+    );
+
+    let breed_data_address = test.breed_data_address(&parents);
+    assert!(
+        fetch_optional::<BreedData>(&mut test.context, &breed_data_address)
+            .await
+            .is_none()
+    );
+
+    let machine = test.machine().await;
+    assert_eq!((machine.bred, machine.born, machine.in_progress), (2, 1, 0));
+    assert_eq!(machine.reserved, 0);
+
+    for parent in &parents {
+        let record: ParentRecord = fetch(
+            &mut test.context,
+            &pda::find_parent_record_address(parent).0,
+        )
+        .await;
+        assert_eq!(record.times_bred, 1);
+    }
+
+    let lineage: Lineage = fetch(
+        &mut test.context,
+        &pda::find_breed_lineage_address(&test.machine, 0).0,
+    )
+    .await;
+    assert_eq!(lineage.parents, parents);
+    assert_eq!(lineage.generation, 1);
+    assert_eq!(lineage.child_mint, None);
+}
+
+#[tokio::test]
+async fn finalize_breeding_burns_parents() {
+    let mut test = Test::new(|config| config.burn_parents = true).await;
+    let parents = test.parents.clone();
+
+    test.init(&parents).await.unwrap();
+    test.finalize().await.unwrap();
+
+    assert_eq!(test.parent_balances().await, vec![Some(0), Some(0)]);
+
+    for parent in &parents {
+        let account = test
+            .context
+            .banks_client
+            .get_account(*parent)
+            .await
+            .unwrap()
+            .unwrap();
+        let mint = spl_token::state::Mint::unpack(&account.data).unwrap();
+        assert_eq!(mint.supply, 0);
+    }
+}
+
+#[tokio::test]
+async fn finalize_breeding_can_fail() {
+    let mut test = Test::new(|config| config.success_bps = 0).await;
+    let parents = test.parents.clone();
+
+    test.init(&parents).await.unwrap();
+    warp_past_reveal(&mut test.context).await;
+    test.finalize().await.unwrap();
+
+    assert_eq!(test.parent_balances().await, vec![Some(1), Some(1)]);
+    let whitelist_ata = test.whitelist_ata();
+    assert_eq!(
+        token_balance(&mut test.context, &whitelist_ata).await,
+        Some(0)
+    );
+
+    let machine = test.machine().await;
+    assert_eq!((machine.bred, machine.born, machine.in_progress), (2, 0, 0));
+
+    let lineage = pda::find_breed_lineage_address(&test.machine, 0).0;
+    assert!(fetch_optional::<Lineage>(&mut test.context, &lineage)
+        .await
+        .is_none());
+}
+
+#[tokio::test]
+async fn replenish_rewards() {
+    let mut test = Test::new(|config| {
+        config.mint_capped = true;
+        config.reward_cap = 15;
+    })
+    .await;
+
+    let ix = instructions::replenish_rewards(&test.machine, &test.authority.pubkey(), 5);
+    process(&mut test.context, &[ix], &[&test.authority])
+        .await
+        .unwrap();
+
+    assert_eq!(test.machine().await.config.reward_supply, 15);
+    let vault = pda::whitelist_vault_address(&test.machine);
+    assert_eq!(token_balance(&mut test.context, &vault).await, Some(15));
+
+    let ix = instructions::replenish_rewards(&test.machine, &test.authority.pubkey(), 1);
+    let result = process(&mut test.context, &[ix], &[&test.authority]).await;
+    assert_error(result, BreedingError::RewardCapExceeded);
+}
+
+#[tokio::test]
+async fn add_reward_tier() {
+    let mut test = Test::new(|_| {}).await;
+
+    test.add_reward_tier(3, 5).await.unwrap();
+
+    let machine = test.machine().await;
+    assert_eq!(machine.base_reward_weight, 1);
+    assert_eq!(machine.extra_reward_tiers.len(), 1);
+    assert_eq!(machine.extra_reward_tiers[0].weight, 3);
+
+    let vault = pda::reward_tier_vault_address(&test.machine, 1);
+    assert_eq!(token_balance(&mut test.context, &vault).await, Some(5));
+}
+
+#[tokio::test]
+async fn finalize_breeding_sends_reward_tier() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = test.parents.clone();
+
+    // Only the extra tier can be picked.
+    test.add_reward_tier(1, 5).await.unwrap();
+    test.set_reward_tier_weight(0, 0).await.unwrap();
+
+    test.init(&parents).await.unwrap();
+    test.finalize().await.unwrap();
+
+    let tier_ata = test.reward_tier_ata(1);
+    assert_eq!(token_balance(&mut test.context, &tier_ata).await, Some(1));
+    let whitelist_ata = test.whitelist_ata();
+    assert_eq!(
+        token_balance(&mut test.context, &whitelist_ata).await,
+        Some(0)
+    );
+}
+
+#[tokio::test]
+async fn empty_reward_tier_weight_is_redistributed() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = test.parents.clone();
+
+    // The extra tier would almost always be picked, but has no tokens.
+    test.add_reward_tier(u16::MAX, 0).await.unwrap();
+
+    test.init(&parents).await.unwrap();
+    test.finalize().await.unwrap();
+
+    let whitelist_ata = test.whitelist_ata();
+    assert_eq!(
+        token_balance(&mut test.context, &whitelist_ata).await,
+        Some(1)
+    );
+    let tier_ata = test.reward_tier_ata(1);
+    assert_eq!(token_balance(&mut test.context, &tier_ata).await, None);
+}
+
+#[tokio::test]
+async fn reward_tiers_added_later_are_not_rolled() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = test.parents.clone();
+    let breed_data_address = test.breed_data_address(&parents);
+
+    test.init(&parents).await.unwrap();
+    let breed_data: BreedData = fetch(&mut test.context, &breed_data_address).await;
+    assert!(!breed_data.rolls);
+
+    // The breeding started with a single tier, so it still doesn't need the
+    // reveal slot hash.
+    test.add_reward_tier(u16::MAX, 5).await.unwrap();
+    test.finalize().await.unwrap();
+
+    let whitelist_ata = test.whitelist_ata();
+    assert_eq!(
+        token_balance(&mut test.context, &whitelist_ata).await,
+        Some(1)
+    );
+}
+
+#[tokio::test]
+async fn cancel_breeding_returns_parents() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = test.parents.clone();
+
+    test.init(&parents).await.unwrap();
+    test.cancel().await.unwrap();
+
+    assert_eq!(test.parent_balances().await, vec![Some(1), Some(1)]);
+
+    let breed_data_address = test.breed_data_address(&parents);
+    assert!(
+        fetch_optional::<BreedData>(&mut test.context, &breed_data_address)
+            .await
+            .is_none()
+    );
+
+    let machine = test.machine().await;
+    assert_eq!(
+        (machine.born, machine.reserved, machine.in_progress),
+        (0, 0, 0)
+    );
+}
+
+#[tokio::test]
+async fn fee_treasury_update_keeps_breedings_in_progress() {
+    let treasury = Pubkey::new_unique();
+    let mut test = Test::new(|config| {
+        config.sol_fee_lamports = SOL;
+        config.sol_fee_treasury = treasury;
+    })
+    .await;
+    let parents = test.parents.clone();
+
+    test.init(&parents).await.unwrap();
+    test.update(UpdateMachineConfigArgs {
+        sol_fee_treasury: Some(Pubkey::new_unique()),
+        ..UpdateMachineConfigArgs::default()
+    })
+    .await
+    .unwrap();
+    test.finalize().await.unwrap();
+
+    let balance = test.context.banks_client.get_balance(treasury).await;
+    assert_eq!(balance.unwrap(), SOL);
+}
+
+#[tokio::test]
+async fn prefunded_parent_record() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = test.parents.clone();
+
+    // Anyone can send lamports to the record address before the first breeding.
+    let (record, _) = pda::find_parent_record_address(&parents[0]);
+    test.context.set_account(
+        &record,
+        &AccountSharedData::from(Account {
+            lamports: 1,
+            ..Account::default()
+        }),
+    );
+
+    test.init(&parents).await.unwrap();
+    let record: ParentRecord = fetch(&mut test.context, &record).await;
+    assert_eq!(record.mint, parents[0]);
+}
+
+#[tokio::test]
+async fn prefunded_lineage() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = test.parents.clone();
+
+    // Anyone can send lamports to the lineage of the next breeding.
+    let (lineage, _) = pda::find_breed_lineage_address(&test.machine, 0);
+    test.context.set_account(
+        &lineage,
+        &AccountSharedData::from(Account {
+            lamports: 1,
+            ..Account::default()
+        }),
+    );
+
+    test.init(&parents).await.unwrap();
+    test.finalize().await.unwrap();
+
+    let lineage: Lineage = fetch(&mut test.context, &lineage).await;
+    assert_eq!(lineage.parents, parents);
+}
+
+#[tokio::test]
+async fn still_in_progress() {
+    let mut test = Test::new(|config| config.breeding_time = 1000).await;
+    let parents = test.parents.clone();
+
+    test.init(&parents).await.unwrap();
+    assert_error(test.finalize().await, BreedingError::StillInProgress);
+
+    warp(&mut test.context, 1000).await;
+    test.finalize().await.unwrap();
+}
+
+#[tokio::test]
+async fn reveal_too_early() {
+    let mut test = Test::new(|config| config.success_bps = 5000).await;
+    let parents = test.parents.clone();
+
+    test.init(&parents).await.unwrap();
+    assert_error(test.finalize().await, BreedingError::RevealTooEarly);
+
+    let ix = instructions::reveal_breeding(&test.machine, &parents);
+    let result = process(&mut test.context, &[ix], &[]).await;
+    assert_error(result, BreedingError::RevealTooEarly);
+
+    warp_past_reveal(&mut test.context).await;
+    test.finalize().await.unwrap();
+}
+
+#[tokio::test]
+async fn reveal_breeding_fixes_the_outcome() {
+    let mut test = Test::new(|config| config.success_bps = 5000).await;
+    let parents = test.parents.clone();
+    let breed_data_address = test.breed_data_address(&parents);
+
+    test.init(&parents).await.unwrap();
+    warp_past_reveal(&mut test.context).await;
+
+    let ix = instructions::reveal_breeding(&test.machine, &parents);
+    process(&mut test.context, &[ix], &[]).await.unwrap();
+    let breed_data: BreedData = fetch(&mut test.context, &breed_data_address).await;
+    let seed = breed_data.seed.unwrap();
+
+    // Later reveals keep the seed.
+    warp(&mut test.context, 0).await;
+    let ix = instructions::reveal_breeding(&test.machine, &parents);
+    process(&mut test.context, &[ix], &[]).await.unwrap();
+    let breed_data: BreedData = fetch(&mut test.context, &breed_data_address).await;
+    assert_eq!(breed_data.seed, Some(seed));
+
+    test.finalize().await.unwrap();
+    let machine = test.machine().await;
+    let succeeded = randomness::roll_success(&seed, 5000);
+    assert_eq!(machine.born, succeeded as u64);
+}
+
+#[tokio::test]
+async fn outcome_revealed() {
+    let mut test = Test::new(|config| config.success_bps = 5000).await;
+    let parents = test.parents.clone();
+
+    // Breedings can be cancelled until their outcome can be known.
+    test.init(&parents).await.unwrap();
+    test.cancel().await.unwrap();
+
+    test.init(&parents).await.unwrap();
+    warp_past_reveal(&mut test.context).await;
+    assert_error(test.cancel().await, BreedingError::OutcomeRevealed);
+
+    test.finalize().await.unwrap();
+}
+
+#[tokio::test]
+async fn rewards_sold_out() {
+    let mut test = Test::new(|config| config.reward_supply = 1).await;
+    let parents = test.parents.clone();
+
+    let creator = test.machine().await.config.parents_candy_machine;
+    let user = test.user.pubkey();
+    let other_parents = pda::sort_parents(&[
+        NftFixture::new()
+            .creator(creator, true)
+            .set_in(&mut test.context, &user),
+        NftFixture::new()
+            .creator(creator, true)
+            .set_in(&mut test.context, &user),
+    ]);
+
+    // The only whitelist token is reserved by the first breeding.
+    test.init(&parents).await.unwrap();
+    assert_error(
+        test.init(&other_parents).await,
+        BreedingError::RewardsSoldOut,
+    );
+
+    // Cancelling releases it.
+    test.cancel().await.unwrap();
+    test.init(&other_parents).await.unwrap();
+}
+
+#[tokio::test]
+async fn invalid_nft_collection() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = [test.parents[0], test.stranger];
+
+    assert_error(
+        test.init(&parents).await,
+        BreedingError::InvalidNftCollection,
+    );
+}
+
+#[tokio::test]
+async fn active_breedings() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = test.parents.clone();
+
+    test.init(&parents).await.unwrap();
+
+    let machine = test.machine().await;
+    let ix = instructions::close_machine(&test.machine, &machine, &test.authority.pubkey());
+    let result = process(&mut test.context, &[ix], &[&test.authority]).await;
+    assert_error(result, BreedingError::ActiveBreedings);
+}
+
+#[tokio::test]
+async fn invalid_breed_owner() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = test.parents.clone();
+
+    test.init(&parents).await.unwrap();
+
+    let machine = test.machine().await;
+    let breed_data_address = test.breed_data_address(&parents);
+    let breed_data = fetch(&mut test.context, &breed_data_address).await;
+    let ix = instructions::cancel_breeding(
+        &test.machine,
+        &machine,
+        &breed_data,
+        &test.authority.pubkey(),
+    );
+    let result = process(&mut test.context, &[ix], &[&test.authority]).await;
+    assert_error(result, BreedingError::InvalidBreedOwner);
+}
+
+#[tokio::test]
+async fn machine_paused() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = test.parents.clone();
+
+    let ix = instructions::pause_machine(&test.machine, &test.authority.pubkey());
+    process(&mut test.context, &[ix], &[&test.authority])
+        .await
+        .unwrap();
+    assert_error(test.init(&parents).await, BreedingError::MachinePaused);
+
+    let ix = instructions::resume_machine(&test.machine, &test.authority.pubkey());
+    process(&mut test.context, &[ix], &[&test.authority])
+        .await
+        .unwrap();
+    test.init(&parents).await.unwrap();
+}
+
+#[tokio::test]
+async fn invalid_pending_authority() {
+    let mut test = Test::new(|_| {}).await;
+    let new_authority = test.user.pubkey();
+
+    let ix =
+        instructions::propose_authority(&test.machine, &test.authority.pubkey(), &new_authority);
+    process(&mut test.context, &[ix], &[&test.authority])
+        .await
+        .unwrap();
+
+    let ix = instructions::accept_authority(&test.machine, &test.authority.pubkey());
+    let result = process(&mut test.context, &[ix], &[&test.authority]).await;
+    assert_error(result, BreedingError::InvalidPendingAuthority);
+
+    let ix = instructions::accept_authority(&test.machine, &new_authority);
+    process(&mut test.context, &[ix], &[&test.user])
+        .await
+        .unwrap();
+    assert_eq!(test.machine().await.authority, new_authority);
+}
+
+#[tokio::test]
+async fn already_migrated() {
+    let mut test = Test::new(|_| {}).await;
+
+    let ix = instructions::migrate_machine(&test.machine, &test.authority.pubkey());
+    let result = process(&mut test.context, &[ix], &[&test.authority]).await;
+    assert_error(result, BreedingError::AlreadyMigrated);
+}
+
+#[tokio::test]
+async fn finalize_legacy_breeding_sends_reward() {
+    let mut test = Test::new(|_| {}).await;
+    let user = test.user.pubkey();
+    let (mint_a, mint_b, breed_data) = test.lock_legacy_parents();
+
+    let whitelist_token = pda::find_whitelist_token_address(&test.machine).0;
+    let ixs = [
+        instructions::create_associated_token_account(&user, &user, &whitelist_token),
+        instructions::finalize_legacy_breeding(&test.machine, &user, &mint_a, &mint_b),
+    ];
+    process(&mut test.context, &ixs, &[&test.user])
+        .await
+        .unwrap();
+
+    assert_eq!(test.parent_balances().await, vec![Some(1), Some(1)]);
+    let whitelist_ata = test.whitelist_ata();
+    assert_eq!(
+        token_balance(&mut test.context, &whitelist_ata).await,
+        Some(1)
+    );
+    assert!(test
+        .context
+        .banks_client
+        .get_account(breed_data)
+        .await
+        .unwrap()
+        .is_none());
+
+    let machine = test.machine().await;
+    assert_eq!((machine.bred, machine.born), (2, 1));
+}
+
+#[tokio::test]
+async fn cancel_legacy_breeding_returns_parents() {
+    let mut test = Test::new(|_| {}).await;
+    let user = test.user.pubkey();
+    let (mint_a, mint_b, breed_data) = test.lock_legacy_parents();
+
+    let ix = instructions::cancel_legacy_breeding(&test.machine, &user, &mint_a, &mint_b);
+    process(&mut test.context, &[ix], &[&test.user])
+        .await
+        .unwrap();
+
+    assert_eq!(test.parent_balances().await, vec![Some(1), Some(1)]);
+    let vault = get_associated_token_address(&breed_data, &mint_a);
+    assert_eq!(token_balance(&mut test.context, &vault).await, None);
+    assert!(test
+        .context
+        .banks_client
+        .get_account(breed_data)
+        .await
+        .unwrap()
+        .is_none());
+}
+
+#[tokio::test]
+async fn client_leaves_out_legacy_breed_data() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = test.parents.clone();
+
+    test.init(&parents).await.unwrap();
+    let breed_data = test.breed_data_address(&parents);
+    let legacy = test.set_legacy_breed_data(Pubkey::new_unique(), Pubkey::new_unique());
+
+    // The program accounts an RPC node would return without the size filter.
+    let mut program_accounts = vec![];
+    for address in [breed_data, legacy].iter() {
+        let account = test.context.banks_client.get_account(*address).await;
+        program_accounts.push((*address, account.unwrap().unwrap().data));
+    }
+
+    let breeds = accounts::decode_all::<BreedData>(program_accounts, 8 + BreedData::LEN).unwrap();
+    assert_eq!(breeds.len(), 1);
+    assert_eq!(breeds[0].0, breed_data);
+    assert_eq!(breeds[0].1.mints, parents);
+}
+
+#[tokio::test]
+async fn parent_in_cooldown() {
+    let mut test = Test::new(|config| config.breeding_cooldown = 1000).await;
+    let parents = test.parents.clone();
+
+    test.init(&parents).await.unwrap();
+    test.finalize().await.unwrap();
+    assert_error(test.init(&parents).await, BreedingError::ParentInCooldown);
+
+    warp(&mut test.context, 1000).await;
+    test.init(&parents).await.unwrap();
+}
+
+#[tokio::test]
+async fn endless_cooldown() {
+    let mut test = Test::new(|config| config.breeding_cooldown = u64::MAX).await;
+    let parents = test.parents.clone();
+
+    test.init(&parents).await.unwrap();
+    test.finalize().await.unwrap();
+    assert_error(test.init(&parents).await, BreedingError::ParentInCooldown);
+}
+
+#[tokio::test]
+async fn max_breeds_reached() {
+    let mut test = Test::new(|config| config.max_breeds_per_parent = 1).await;
+    let parents = test.parents.clone();
+
+    test.init(&parents).await.unwrap();
+    test.finalize().await.unwrap();
+    assert_error(test.init(&parents).await, BreedingError::MaxBreedsReached);
+}
+
+#[tokio::test]
+async fn self_breeding() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = [test.parents[0], test.parents[0]];
+
+    assert_error(test.init(&parents).await, BreedingError::SelfBreeding);
+}
+
+#[tokio::test]
+async fn unsorted_parents() {
+    let mut test = Test::new(|_| {}).await;
+    let machine = test.machine().await;
+    let parents = test.parents.clone();
+    let unsorted: Vec<Pubkey> = parents.iter().rev().copied().collect();
+
+    // The client sorts the parents, so unsort the instruction it builds.
+    let mut ix =
+        instructions::initialize_breeding(&test.machine, &machine, &test.user.pubkey(), &parents);
+    ix.accounts[1].pubkey = Pubkey::find_program_address(
+        &[
+            BreedData::PREFIX,
+            test.machine.as_ref(),
+            unsorted[0].as_ref(),
+        ],
+        &breed_program::ID,
+    )
+    .0;
+    ix.data = breed_program::instruction::InitializeBreeding { parents: unsorted }.data();
+
+    let result = process(&mut test.context, &[ix], &[&test.user]).await;
+    assert_error(result, BreedingError::UnsortedParents);
+}
+
+#[tokio::test]
+async fn too_many_parent_collections() {
+    let mut test = Test::new(|_| {}).await;
+    let collections = (0..MAX_PARENT_COLLECTIONS)
+        .map(|_| ParentCollection {
+            creator: Pubkey::new_unique(),
+            collection: Pubkey::default(),
+        })
+        .collect();
+
+    let ix = instructions::set_parent_collections(
+        &test.machine,
+        &test.authority.pubkey(),
+        collections,
+        None,
+    );
+    let result = process(&mut test.context, &[ix], &[&test.authority]).await;
+    assert_error(result, BreedingError::TooManyParentCollections);
+}
+
+#[tokio::test]
+async fn incompatible_parents() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = [test.parents[0], test.other_parent];
+
+    // Each collection only breeds with itself.
+    let mut compatibility = [0; MAX_PARENT_COLLECTIONS];
+    compatibility[0] = 0b01;
+    compatibility[1] = 0b10;
+
+    let collections = vec![ParentCollection {
+        creator: test.other_creator,
+        collection: Pubkey::default(),
+    }];
+    let ix = instructions::set_parent_collections(
+        &test.machine,
+        &test.authority.pubkey(),
+        collections,
+        Some(compatibility),
+    );
+    process(&mut test.context, &[ix], &[&test.authority])
+        .await
+        .unwrap();
+
+    assert_error(
+        test.init(&parents).await,
+        BreedingError::IncompatibleParents,
+    );
+}
+
+#[tokio::test]
+async fn invalid_basis_points() {
+    let mut test = Test::new(|_| {}).await;
+
+    let result = test
+        .update(UpdateMachineConfigArgs {
+            fee_mode: Some(FeeMode::Split { burn_bps: 10_001 }),
+            ..UpdateMachineConfigArgs::default()
+        })
+        .await;
+    assert_error(result, BreedingError::InvalidBasisPoints);
+}
+
+#[tokio::test]
+async fn invalid_fee_treasury() {
+    let mut test = Test::new(|_| {}).await;
+
+    let result = test
+        .update(UpdateMachineConfigArgs {
+            sol_fee_lamports: Some(SOL),
+            ..UpdateMachineConfigArgs::default()
+        })
+        .await;
+    assert_error(result, BreedingError::InvalidFeeTreasury);
+
+    let result = test
+        .update(UpdateMachineConfigArgs {
+            initialization_fee_price: Some(1),
+            fee_mode: Some(FeeMode::Treasury),
+            ..UpdateMachineConfigArgs::default()
+        })
+        .await;
+    assert_error(result, BreedingError::InvalidFeeTreasury);
+}
+
+#[tokio::test]
+async fn invalid_parent_count() {
+    let mut test = Test::new(|_| {}).await;
+    let parent = test.parents[0];
+
+    let result = test
+        .update(UpdateMachineConfigArgs {
+            parents_count: Some(0),
+            ..UpdateMachineConfigArgs::default()
+        })
+        .await;
+    assert_error(result, BreedingError::InvalidParentCount);
+
+    assert_error(
+        test.init(&[parent]).await,
+        BreedingError::InvalidParentCount,
+    );
+}
+
+fn child_nft(collection: Pubkey) -> ChildNft {
+    ChildNft {
+        collection,
+        name: "Child".to_string(),
+        symbol: "CHILD".to_string(),
+        uri_prefix: "https://example.com/".to_string(),
+        seller_fee_basis_points: 500,
+    }
+}
+
+#[tokio::test]
+async fn invalid_child_nft() {
+    let mut test = Test::new(|_| {}).await;
+
+    let child_nft = ChildNft {
+        name: "A name that doesn't fit the metadata".to_string(),
+        ..child_nft(Pubkey::new_unique())
+    };
+    let ix = instructions::set_child_nft(&test.machine, &test.authority.pubkey(), Some(child_nft));
+    let result = process(&mut test.context, &[ix], &[&test.authority]).await;
+    assert_error(result, BreedingError::InvalidChildNft);
+
+    let child_nft = ChildNft {
+        uri_prefix: "a".repeat(ChildNft::MAX_URI_PREFIX_LEN + 1),
+        ..child_nft(Pubkey::new_unique())
+    };
+    let ix = instructions::set_child_nft(&test.machine, &test.authority.pubkey(), Some(child_nft));
+    let result = process(&mut test.context, &[ix], &[&test.authority]).await;
+    assert_error(result, BreedingError::InvalidChildNft);
+}
+
+#[tokio::test]
+async fn invalid_reward_collection() {
+    let mut test = Test::new(|_| {}).await;
+    let parents = test.parents.clone();
+    let collection = Pubkey::new_unique();
+
+    let ix = instructions::set_child_nft(
+        &test.machine,
+        &test.authority.pubkey(),
+        Some(child_nft(collection)),
+    );
+    process(&mut test.context, &[ix], &[&test.authority])
+        .await
+        .unwrap();
+    test.init(&parents).await.unwrap();
+
+    let machine = test.machine().await;
+    let breed_data_address = test.breed_data_address(&parents);
+    let breed_data = fetch(&mut test.context, &breed_data_address).await;
+    let mut ix =
+        instructions::finalize_breeding(&test.machine, &machine, &breed_data, &test.user.pubkey());
+
+    // Fails before minting, so the metadata program isn't needed.
+    for account in &mut ix.accounts {
+        if account.pubkey == collection {
+            account.pubkey = Pubkey::new_unique();
+        }
+    }
+
+    let result = process(&mut test.context, &[ix], &[&test.user]).await;
+    assert_error(result, BreedingError::InvalidRewardCollection);
+}
+
+#[tokio::test]
+async fn invalid_reward_tier() {
+    let mut test = Test::new(|_| {}).await;
+
+    assert_error(
+        test.set_reward_tier_weight(1, 1).await,
+        BreedingError::InvalidRewardTier,
+    );
+
+    test.add_reward_tier(1, 5).await.unwrap();
+    test.add_reward_tier(1, 5).await.unwrap();
+    assert_error(
+        test.add_reward_tier(1, 5).await,
+        BreedingError::InvalidRewardTier,
+    );
+}
+
+#[tokio::test]
+async fn not_mint_capped() {
+    let mut test = Test::new(|_| {}).await;
+
+    let ix = instructions::replenish_rewards(&test.machine, &test.authority.pubkey(), 5);
+    let result = process(&mut test.context, &[ix], &[&test.authority]).await;
+    assert_error(result, BreedingError::NotMintCapped);
+}
@@ -0,0 +1,164 @@
+//! Outcome rolls of `breed_program::randomness`, without running the program.
+
+use anchor_lang::{
+    error::Error,
+    prelude::{AccountInfo, Pubkey},
+    solana_program::hash::hashv,
+};
+use breed_program::{randomness::*, BreedingError, MAX_BPS};
+
+fn seed(i: u32) -> [u8; 32] {
+    hashv(&[&i.to_le_bytes()]).to_bytes()
+}
+
+#[test]
+fn rolls_below_max_bps() {
+    assert!((0..1000).all(|i| roll_bps(&seed(i)) < MAX_BPS));
+    assert_eq!(roll_bps(&seed(7)), roll_bps(&seed(7)));
+}
+
+#[test]
+fn always_or_never_succeeds() {
+    assert!((0..1000).all(|i| roll_success(&seed(i), MAX_BPS)));
+    assert!((0..1000).all(|i| !roll_success(&seed(i), 0)));
+}
+
+#[test]
+fn succeeds_below_the_odds() {
+    let seed = seed(42);
+    let bps = roll_bps(&seed);
+
+    assert!(roll_success(&seed, bps + 1));
+    assert!(!roll_success(&seed, bps));
+}
+
+#[test]
+fn follows_the_odds() {
+    let successes = (0..10_000)
+        .filter(|i| roll_success(&seed(*i), 3000))
+        .count();
+
+    assert!((2800..3200).contains(&successes), "{} successes", successes);
+}
+
+#[test]
+fn reveal_mixes_commitment_and_slot_hash() {
+    let breed_data = Pubkey::new_unique();
+    let now = 1_650_000_000;
+    let committed = commitment(&breed_data, 10, now);
+
+    assert_ne!(committed, commitment(&breed_data, 11, now));
+    assert_ne!(committed, commitment(&Pubkey::new_unique(), 10, now));
+
+    let slot_hash = [3; 32];
+    assert_eq!(
+        reveal(&committed, &slot_hash),
+        reveal(&committed, &slot_hash)
+    );
+    assert_ne!(reveal(&committed, &slot_hash), reveal(&committed, &[4; 32]));
+    assert_ne!(reveal(&committed, &slot_hash), reveal(&[0; 32], &slot_hash));
+}
+
+/// `SlotHashes` account data with these entries, most recent first.
+fn slot_hashes_data(entries: &[(u64, [u8; 32])]) -> Vec<u8> {
+    let mut data = (entries.len() as u64).to_le_bytes().to_vec();
+    for (slot, hash) in entries {
+        data.extend_from_slice(&slot.to_le_bytes());
+        data.extend_from_slice(hash);
+    }
+    // The sysvar account always has room for 512 entries.
+    data.resize(8 + 512 * 40, 0);
+
+    data
+}
+
+fn read(mut data: Vec<u8>, slot: u64) -> anchor_lang::Result<Option<[u8; 32]>> {
+    let key = Pubkey::new_unique();
+    let owner = Pubkey::new_unique();
+    let mut lamports = 0;
+    let info = AccountInfo::new(
+        &key,
+        false,
+        false,
+        &mut lamports,
+        &mut data,
+        &owner,
+        false,
+        0,
+    );
+
+    slot_hash_at(&info, slot)
+}
+
+fn assert_breeding_error(result: anchor_lang::Result<Option<[u8; 32]>>, expected: BreedingError) {
+    match result {
+        Err(Error::AnchorError(error)) => {
+            assert_eq!(error.error_code_number, u32::from(expected))
+        }
+        other => panic!("expected {:?}, got {:?}", expected, other),
+    }
+}
+
+#[test]
+fn reveals_with_the_hash_of_the_reveal_slot() {
+    assert_eq!(reveal_slot(10), 10 + REVEAL_DELAY);
+
+    let data = slot_hashes_data(&[(13, [1; 32]), (12, [2; 32]), (11, [3; 32])]);
+    assert_eq!(read(data, 12).unwrap(), Some([2; 32]));
+}
+
+#[test]
+fn skipped_reveal_slots_take_the_next_hash() {
+    let data = slot_hashes_data(&[(14, [1; 32]), (13, [2; 32]), (11, [3; 32])]);
+
+    assert_eq!(read(data, 12).unwrap(), Some([2; 32]));
+}
+
+#[test]
+fn rejects_reveal_slots_not_produced_yet() {
+    let data = slot_hashes_data(&[(12, [1; 32]), (11, [2; 32])]);
+    assert_breeding_error(read(data, 13), BreedingError::RevealTooEarly);
+
+    assert_breeding_error(
+        read(slot_hashes_data(&[]), 0),
+        BreedingError::RevealTooEarly,
+    );
+}
+
+#[test]
+fn reveal_slots_that_left_the_sysvar_have_no_hash() {
+    let entries: Vec<_> = (0..512).map(|i| (1000 - i, [1; 32])).collect();
+
+    // The oldest entry is 489, the hash of 488 may have been dropped.
+    assert_eq!(read(slot_hashes_data(&entries), 488).unwrap(), None);
+    assert_eq!(
+        read(slot_hashes_data(&entries), 489).unwrap(),
+        Some([1; 32])
+    );
+}
+
+#[test]
+fn outcome_is_unknown_before_the_reveal_slot() {
+    assert!(before_reveal(10, 10));
+    assert!(!before_reveal(10, reveal_slot(10)));
+    assert!(!before_reveal(10, reveal_slot(10) + 1));
+}
+
+#[test]
+fn picks_tiers_with_weight() {
+    assert_eq!(pick_tier(&seed(0), &[]), None);
+    assert_eq!(pick_tier(&seed(0), &[0, 0]), None);
+    assert!((0..1000).all(|i| pick_tier(&seed(i), &[0, 3, 0]) == Some(1)));
+}
+
+#[test]
+fn picks_tiers_by_weight() {
+    let mut picks = [0; 3];
+    for i in 0..10_000 {
+        picks[pick_tier(&seed(i), &[6, 3, 1]).unwrap()] += 1;
+    }
+
+    assert!((5800..6200).contains(&picks[0]), "{:?} picks", picks);
+    assert!((2800..3200).contains(&picks[1]), "{:?} picks", picks);
+    assert!((800..1200).contains(&picks[2]), "{:?} picks", picks);
+}
@@ -0,0 +1,200 @@
+//! Builds NFTs with their Metaplex metadata, as if minted by a candy machine.
+
+use super::SOL;
+use anchor_lang::{prelude::Pubkey, AnchorSerialize};
+use anchor_spl::{associated_token::get_associated_token_address, token::spl_token};
+use mpl_token_metadata::{
+    pda::find_metadata_account,
+    state::{Collection, Creator, Data, Key, Metadata, MAX_METADATA_LEN},
+};
+use solana_program_test::{ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::{Account, AccountSharedData},
+    program_option::COption,
+    program_pack::Pack,
+};
+
+/// An NFT with no creators nor collection until they're added.
+pub struct NftFixture {
+    pub mint: Pubkey,
+    pub name: String,
+    pub symbol: String,
+    pub uri: String,
+    pub creators: Vec<Creator>,
+    pub collection: Option<Collection>,
+    // Off-chain attributes, served at `uri`.
+    pub attributes: Vec<(String, String)>,
+    // Owner of the metadata account, to fake metadata from another program.
+    pub metadata_owner: Pubkey,
+}
+
+impl Default for NftFixture {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl NftFixture {
+    pub fn new() -> Self {
+        let mint = Pubkey::new_unique();
+
+        Self {
+            mint,
+            name: "Parent".to_string(),
+            symbol: String::new(),
+            uri: format!("https://example.com/{}.json", mint),
+            creators: vec![],
+            collection: None,
+            attributes: vec![],
+            metadata_owner: mpl_token_metadata::ID,
+        }
+    }
+
+    /// Adds a creator. Shares are split evenly between the creators.
+    pub fn creator(mut self, address: Pubkey, verified: bool) -> Self {
+        self.creators.push(Creator {
+            address,
+            verified,
+            share: 0,
+        });
+        self
+    }
+
+    pub fn collection(mut self, key: Pubkey, verified: bool) -> Self {
+        self.collection = Some(Collection { key, verified });
+        self
+    }
+
+    pub fn attribute(mut self, trait_type: &str, value: &str) -> Self {
+        self.attributes
+            .push((trait_type.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn metadata_owner(mut self, owner: Pubkey) -> Self {
+        self.metadata_owner = owner;
+        self
+    }
+
+    pub fn metadata(&self) -> Metadata {
+        let creators = match self.creators.len() {
+            0 => None,
+            count => Some(
+                self.creators
+                    .iter()
+                    .enumerate()
+                    .map(|(i, creator)| Creator {
+                        // The first creator takes the remainder.
+                        share: (100 / count + if i == 0 { 100 % count } else { 0 }) as u8,
+                        ..creator.clone()
+                    })
+                    .collect(),
+            ),
+        };
+
+        Metadata {
+            key: Key::MetadataV1,
+            update_authority: self
+                .creators
+                .first()
+                .map_or_else(Pubkey::new_unique, |creator| creator.address),
+            mint: self.mint,
+            data: Data {
+                name: self.name.clone(),
+                symbol: self.symbol.clone(),
+                uri: self.uri.clone(),
+                seller_fee_basis_points: 0,
+                creators,
+            },
+            primary_sale_happened: true,
+            is_mutable: true,
+            edition_nonce: None,
+            token_standard: None,
+            collection: self.collection.clone(),
+            uses: None,
+        }
+    }
+
+    /// The JSON served at `uri`, with the attributes.
+    pub fn off_chain_json(&self) -> String {
+        let attributes: Vec<_> = self
+            .attributes
+            .iter()
+            .map(|(trait_type, value)| {
+                serde_json::json!({ "trait_type": trait_type, "value": value })
+            })
+            .collect();
+
+        serde_json::json!({
+            "name": self.name,
+            "symbol": self.symbol,
+            "attributes": attributes,
+        })
+        .to_string()
+    }
+
+    /// Mint, ATA of `owner` holding the NFT and metadata accounts.
+    pub fn accounts(&self, owner: &Pubkey) -> Vec<(Pubkey, Account)> {
+        let mut mint = vec![0; spl_token::state::Mint::LEN];
+        spl_token::state::Mint {
+            mint_authority: COption::None,
+            supply: 1,
+            decimals: 0,
+            is_initialized: true,
+            freeze_authority: COption::None,
+        }
+        .pack_into_slice(&mut mint);
+
+        let mut ata = vec![0; spl_token::state::Account::LEN];
+        spl_token::state::Account {
+            mint: self.mint,
+            owner: *owner,
+            amount: 1,
+            state: spl_token::state::AccountState::Initialized,
+            ..spl_token::state::Account::default()
+        }
+        .pack_into_slice(&mut ata);
+
+        let mut metadata = self.metadata().try_to_vec().unwrap();
+        metadata.resize(MAX_METADATA_LEN, 0);
+
+        vec![
+            (self.mint, account(mint, spl_token::ID)),
+            (
+                get_associated_token_address(owner, &self.mint),
+                account(ata, spl_token::ID),
+            ),
+            (
+                find_metadata_account(&self.mint).0,
+                account(metadata, self.metadata_owner),
+            ),
+        ]
+    }
+
+    /// Adds the NFT to the genesis, held by `owner`. Returns the mint.
+    pub fn add_to(&self, test: &mut ProgramTest, owner: &Pubkey) -> Pubkey {
+        for (address, account) in self.accounts(owner) {
+            test.add_account(address, account);
+        }
+
+        self.mint
+    }
+
+    /// Adds the NFT to a running test, held by `owner`. Returns the mint.
+    pub fn set_in(&self, context: &mut ProgramTestContext, owner: &Pubkey) -> Pubkey {
+        for (address, account) in self.accounts(owner) {
+            context.set_account(&address, &AccountSharedData::from(account));
+        }
+
+        self.mint
+    }
+}
+
+fn account(data: Vec<u8>, owner: Pubkey) -> Account {
+    Account {
+        lamports: SOL,
+        data,
+        owner,
+        ..Account::default()
+    }
+}
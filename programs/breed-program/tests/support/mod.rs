@@ -0,0 +1,185 @@
+//! Offline test environment: the program runs natively in `ProgramTest`, with
+//! fake parent NFTs and Metaplex metadata accounts added to the genesis.
+
+#![allow(dead_code)]
+
+mod metadata;
+
+pub use metadata::NftFixture;
+
+use anchor_lang::{prelude::Pubkey, AccountDeserialize};
+use anchor_spl::token::spl_token;
+use breed_program::{
+    instructions::{ParentAccounts, RewardTierAccounts},
+    randomness::REVEAL_DELAY,
+    BreedConfig, CancelPolicy, FeeMode, LockMode, ParentValidation, MAX_BPS,
+};
+use solana_program_test::{processor, ProgramTest, ProgramTestContext};
+use solana_sdk::{
+    account::Account,
+    clock::Clock,
+    instruction::{Instruction, InstructionError},
+    program_pack::Pack,
+    signature::{Keypair, Signer},
+    transaction::{Transaction, TransactionError},
+    transport::TransportError,
+};
+
+pub const SOL: u64 = 1_000_000_000;
+
+pub fn program_test() -> ProgramTest {
+    ProgramTest::new(
+        "breed_program",
+        breed_program::ID,
+        processor!(breed_program::entry),
+    )
+}
+
+/// Adds a wallet with 10 SOL.
+pub fn add_wallet(test: &mut ProgramTest) -> Keypair {
+    let wallet = Keypair::new();
+    test.add_account(
+        wallet.pubkey(),
+        Account {
+            lamports: 10 * SOL,
+            ..Account::default()
+        },
+    );
+
+    wallet
+}
+
+/// Adds an NFT owned by `owner`, with `creator` as verified creator.
+pub fn add_parent_nft(test: &mut ProgramTest, owner: &Pubkey, creator: &Pubkey) -> Pubkey {
+    NftFixture::new()
+        .creator(*creator, true)
+        .add_to(test, owner)
+}
+
+/// Config breeding immediately, without fees nor parent limits.
+pub fn default_config(parents_candy_machine: Pubkey) -> BreedConfig {
+    BreedConfig {
+        breeding_time: 0,
+        burn_parents: false,
+        parents_candy_machine,
+        parents_collection: Pubkey::default(),
+        parent_validation: ParentValidation::VerifiedCreator,
+        parents_count: 2,
+        reward_candy_machine: Pubkey::new_unique(),
+        initialization_fee_token: Pubkey::default(),
+        initialization_fee_price: 0,
+        fee_mode: FeeMode::Burn,
+        fee_treasury: Pubkey::default(),
+        sol_fee_lamports: 0,
+        sol_fee_treasury: Pubkey::default(),
+        cancel_policy: CancelPolicy::FullRefund,
+        reward_supply: 10,
+        breeding_cooldown: 0,
+        max_breeds_per_parent: 0,
+        lock_mode: LockMode::Vault,
+        success_bps: MAX_BPS,
+        mint_capped: false,
+        reward_cap: 0,
+    }
+}
+
+/// Sends the instructions in a new transaction paid by the context payer.
+pub async fn process(
+    context: &mut ProgramTestContext,
+    instructions: &[Instruction],
+    signers: &[&Keypair],
+) -> Result<(), TransportError> {
+    // Identical transactions sent twice need different blockhashes.
+    let blockhash = context.get_new_latest_blockhash().await?;
+
+    let mut all_signers = vec![&context.payer];
+    all_signers.extend_from_slice(signers);
+
+    let tx = Transaction::new_signed_with_payer(
+        instructions,
+        Some(&context.payer.pubkey()),
+        &all_signers,
+        blockhash,
+    );
+
+    context.banks_client.process_transaction(tx).await
+}
+
+/// Moves the clock `seconds` forward.
+pub async fn warp(context: &mut ProgramTestContext, seconds: i64) {
+    let slot = context.banks_client.get_root_slot().await.unwrap();
+    context.warp_to_slot(slot + 2).unwrap();
+
+    let mut clock: Clock = context.banks_client.get_sysvar().await.unwrap();
+    clock.unix_timestamp += seconds;
+    context.set_sysvar(&clock);
+}
+
+pub async fn fetch_optional<T: AccountDeserialize>(
+    context: &mut ProgramTestContext,
+    address: &Pubkey,
+) -> Option<T> {
+    context
+        .banks_client
+        .get_account(*address)
+        .await
+        .unwrap()
+        .map(|account| T::try_deserialize(&mut &account.data[..]).unwrap())
+}
+
+pub async fn fetch<T: AccountDeserialize>(context: &mut ProgramTestContext, address: &Pubkey) -> T {
+    fetch_optional(context, address)
+        .await
+        .unwrap_or_else(|| panic!("account {} not found", address))
+}
+
+/// Token balance, None when the token account doesn't exist.
+pub async fn token_balance(context: &mut ProgramTestContext, address: &Pubkey) -> Option<u64> {
+    context
+        .banks_client
+        .get_account(*address)
+        .await
+        .unwrap()
+        .filter(|account| !account.data.is_empty())
+        .map(|account| {
+            spl_token::state::Account::unpack(&account.data)
+                .unwrap()
+                .amount
+        })
+}
+
+/// Moves past the reveal slot of breedings started in the current slot. Each
+/// warp only adds the hash of the slot it leaves to `SlotHashes`.
+pub async fn warp_past_reveal(context: &mut ProgramTestContext) {
+    for _ in 0..=REVEAL_DELAY {
+        warp(context, 0).await;
+    }
+}
+
+/// Index of the first account of the `parent`th parent in an
+/// `initialize_breeding` instruction, see `ParentAccounts`. Parent accounts
+/// follow the named accounts, and the accounts of the `tiers` extra reward
+/// tiers follow them.
+pub fn init_parent_accounts_index(
+    ix: &Instruction,
+    parents: usize,
+    tiers: usize,
+    parent: usize,
+) -> usize {
+    let parent_accounts = parents * ParentAccounts::INIT_LEN;
+    let named_accounts = ix.accounts.len() - parent_accounts - tiers * RewardTierAccounts::INIT_LEN;
+
+    named_accounts + parent * ParentAccounts::INIT_LEN
+}
+
+/// Asserts the transaction failed with a program or framework error.
+pub fn assert_error(result: Result<(), TransportError>, error: impl Into<u32>) {
+    let code = error.into();
+    match result {
+        Err(TransportError::TransactionError(TransactionError::InstructionError(
+            _,
+            InstructionError::Custom(error),
+        ))) => assert_eq!(error, code, "unexpected error code"),
+        other => panic!("expected error code {}, got {:?}", code, other),
+    }
+}
@@ -0,0 +1,153 @@
+//! Parents rejected by `InitializeBreed::validate_nfts`.
+
+mod support;
+
+use anchor_lang::{error::ErrorCode, prelude::Pubkey};
+use breed_client::{instructions, pda};
+use breed_program::{instructions::ParentAccounts, BreedMachine, BreedingError, ParentValidation};
+use solana_sdk::{instruction::Instruction, signature::Signer, transport::TransportError};
+use support::*;
+
+const CREATOR: Pubkey = Pubkey::new_from_array([1; 32]);
+const COLLECTION: Pubkey = Pubkey::new_from_array([2; 32]);
+
+/// Tries to breed the two NFTs on a machine of `CREATOR` and `COLLECTION`,
+/// after `tamper` changes the instruction.
+async fn breed_with(
+    validation: ParentValidation,
+    parents: [NftFixture; 2],
+    tamper: impl FnOnce(&mut Instruction),
+) -> Result<(), TransportError> {
+    let mut test = program_test();
+    let authority = add_wallet(&mut test);
+    let user = add_wallet(&mut test);
+    let mints: Vec<Pubkey> = parents
+        .iter()
+        .map(|nft| nft.add_to(&mut test, &user.pubkey()))
+        .collect();
+
+    let mut context = test.start_with_context().await;
+
+    let mut config = default_config(CREATOR);
+    config.parents_collection = COLLECTION;
+    config.parent_validation = validation;
+
+    let (machine, _) = pda::find_breeding_machine_address(
+        &config.parents_candy_machine,
+        &config.reward_candy_machine,
+        &authority.pubkey(),
+    );
+    let ix = instructions::create_machine(&authority.pubkey(), config);
+    process(&mut context, &[ix], &[&authority]).await.unwrap();
+
+    let machine_account: BreedMachine = fetch(&mut context, &machine).await;
+    let mut ix =
+        instructions::initialize_breeding(&machine, &machine_account, &user.pubkey(), &mints);
+    tamper(&mut ix);
+
+    process(&mut context, &[ix], &[&user]).await
+}
+
+async fn breed(
+    validation: ParentValidation,
+    parents: [NftFixture; 2],
+) -> Result<(), TransportError> {
+    breed_with(validation, parents, |_| {}).await
+}
+
+fn parent() -> NftFixture {
+    NftFixture::new()
+        .creator(CREATOR, true)
+        .collection(COLLECTION, true)
+        .attribute("Background", "Blue")
+}
+
+#[tokio::test]
+async fn accepts_verified_parents() {
+    breed(ParentValidation::Both, [parent(), parent()])
+        .await
+        .unwrap();
+}
+
+#[tokio::test]
+async fn rejects_unverified_creator() {
+    let unverified = NftFixture::new()
+        .creator(CREATOR, false)
+        .collection(COLLECTION, true);
+
+    let result = breed(ParentValidation::VerifiedCreator, [parent(), unverified]).await;
+    assert_error(result, BreedingError::InvalidNftCollection);
+}
+
+#[tokio::test]
+async fn rejects_missing_creators() {
+    let no_creators = NftFixture::new().collection(COLLECTION, true);
+
+    let result = breed(ParentValidation::VerifiedCreator, [parent(), no_creators]).await;
+    assert_error(result, BreedingError::InvalidNftCollection);
+}
+
+#[tokio::test]
+async fn rejects_other_verified_creator() {
+    // The machine creator is listed, but only another creator is verified.
+    let other_creator = NftFixture::new()
+        .creator(Pubkey::new_unique(), true)
+        .creator(CREATOR, false);
+
+    let result = breed(ParentValidation::VerifiedCreator, [parent(), other_creator]).await;
+    assert_error(result, BreedingError::InvalidNftCollection);
+}
+
+#[tokio::test]
+async fn rejects_unverified_collection() {
+    let unverified = NftFixture::new()
+        .creator(CREATOR, true)
+        .collection(COLLECTION, false);
+
+    let result = breed(ParentValidation::VerifiedCollection, [parent(), unverified]).await;
+    assert_error(result, BreedingError::InvalidNftCollection);
+}
+
+#[tokio::test]
+async fn rejects_other_collection() {
+    let other_collection = NftFixture::new()
+        .creator(CREATOR, true)
+        .collection(Pubkey::new_unique(), true);
+
+    let result = breed(
+        ParentValidation::VerifiedCollection,
+        [parent(), other_collection],
+    )
+    .await;
+    assert_error(result, BreedingError::InvalidNftCollection);
+}
+
+#[tokio::test]
+async fn requires_both_checks() {
+    let no_collection = NftFixture::new().creator(CREATOR, true);
+
+    let result = breed(ParentValidation::Both, [parent(), no_collection]).await;
+    assert_error(result, BreedingError::InvalidNftCollection);
+}
+
+#[tokio::test]
+async fn rejects_metadata_of_another_mint() {
+    let result = breed_with(ParentValidation::Both, [parent(), parent()], |ix| {
+        // Metadata is the last account of each parent.
+        let metadata = ParentAccounts::INIT_LEN - 1;
+        let first_metadata = init_parent_accounts_index(ix, 2, 0, 0) + metadata;
+        let second_metadata = init_parent_accounts_index(ix, 2, 0, 1) + metadata;
+
+        ix.accounts[first_metadata] = ix.accounts[second_metadata].clone();
+    })
+    .await;
+    assert_error(result, ErrorCode::ConstraintRaw);
+}
+
+#[tokio::test]
+async fn rejects_metadata_of_another_program() {
+    let fake = parent().metadata_owner(Pubkey::new_unique());
+
+    let result = breed(ParentValidation::Both, [parent(), fake]).await;
+    assert_error(result, ErrorCode::AccountOwnedByWrongProgram);
+}
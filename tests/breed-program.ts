@@ -4,8 +4,12 @@ import { expect } from "chai"
 
 import {
   createBreeding,
+  findBreedLineageAddress,
   findBreedingMachineAddress,
+  findParentRecordAddress,
+  findRewardTierTokenAddress,
   findWhitelistTokenAddress,
+  getRewardTierAccounts,
 } from "../app/utils/breeding"
 import { BreedProgram } from "../target/types/breed_program"
 
@@ -58,13 +62,36 @@ describe("breed-program", () => {
     program.programId
   )
 
-  const { init, terminate, cancel } = createBreeding(
+  const { init, revealWhenReady, terminate, cancel } = createBreeding(
     program.provider.connection,
     program as any,
     breedingMachine,
     userWallet
   )
 
+  const thirdParty = anchor.web3.Keypair.generate()
+
+  const thirdPartyBreeding = createBreeding(
+    program.provider.connection,
+    program as any,
+    breedingMachine,
+    thirdParty
+  )
+
+  const expectBreedingError = async (
+    promise: Promise<unknown>,
+    code: string
+  ) => {
+    try {
+      await promise
+    } catch (e) {
+      expect(e.error?.errorCode?.code).to.equal(code)
+      return
+    }
+
+    expect.fail(`Expected ${code} error`)
+  }
+
   it("should be able to create a new breeding machine", async () => {
     const config = {
       burnParents: false,
@@ -73,8 +100,22 @@ describe("breed-program", () => {
       rewardSupply: new anchor.BN(3333),
       initializationFeeToken: feeToken,
       initializationFeePrice: new anchor.BN(1),
+      feeMode: { burn: {} },
+      feeTreasury: anchor.web3.PublicKey.default,
+      solFeeLamports: new anchor.BN(0),
+      solFeeTreasury: anchor.web3.PublicKey.default,
+      cancelPolicy: { fullRefund: {} },
+      lockMode: { vault: {} },
+      successBps: 10000,
+      mintCapped: true,
+      rewardCap: new anchor.BN(3343),
       rewardCandyMachine: rewardsCandyMachineAddress,
       parentsCandyMachine: parentsCandyMachineAddress,
+      parentsCollection: anchor.web3.PublicKey.default,
+      parentValidation: { verifiedCreator: {} },
+      parentsCount: 2,
+      breedingCooldown: new anchor.BN(0),
+      maxBreedsPerParent: new anchor.BN(0),
     }
 
     const whitelistVault = await anchor.utils.token.associatedAddress({
@@ -120,6 +161,18 @@ describe("breed-program", () => {
         initializationFeePrice: newPrice,
         breedingTime: new anchor.BN(0),
         burnParents: false,
+        breedingCooldown: null,
+        maxBreedsPerParent: null,
+        parentsCollection: null,
+        parentValidation: null,
+        parentsCount: null,
+        feeMode: null,
+        feeTreasury: null,
+        solFeeLamports: null,
+        solFeeTreasury: null,
+        cancelPolicy: null,
+        lockMode: null,
+        successBps: null,
       })
       .accounts({
         breedingMachine,
@@ -139,14 +192,50 @@ describe("breed-program", () => {
     )
   })
 
+  it("should emit an event when the config is updated", async () => {
+    let listener: number
+    const event = new Promise<any>((resolve) => {
+      listener = program.addEventListener("ConfigUpdated", resolve)
+    })
+
+    await program.methods
+      .updateMachineConfig({
+        initializationFeePrice: null,
+        breedingTime: null,
+        burnParents: null,
+        breedingCooldown: null,
+        maxBreedsPerParent: null,
+        parentsCollection: null,
+        parentValidation: null,
+        parentsCount: null,
+        feeMode: null,
+        feeTreasury: null,
+        solFeeLamports: null,
+        solFeeTreasury: null,
+        cancelPolicy: null,
+        lockMode: null,
+        successBps: null,
+      })
+      .accounts({
+        breedingMachine,
+        authority: breedingMachineAuthority.publicKey,
+      })
+      .signers([breedingMachineAuthority])
+      .rpc()
+
+    const { machine, config } = await event
+    await program.removeEventListener(listener)
+
+    expect(machine.toBase58()).to.equal(breedingMachine.toBase58())
+    expect(config.initializationFeePrice.toNumber()).to.equal(1234)
+  })
+
   it("should be able to initialize a breeding", async () => {
     const {
       tx,
-      userAtaParentA,
-      userAtaParentB,
-      vaultAtaParentA,
-      vaultAtaParentB,
-    } = await init(mintParentA, mintParentB, [userWallet])
+      userAtas: [userAtaParentA, userAtaParentB],
+      vaultAtas: [vaultAtaParentA, vaultAtaParentB],
+    } = await init([mintParentA, mintParentB], [userWallet])
 
     console.log("Your transaction signature", tx)
 
@@ -162,15 +251,25 @@ describe("breed-program", () => {
     const breedMintBBalance =
       await program.provider.connection.getTokenAccountBalance(vaultAtaParentB)
 
+    const { reserved } = await program.account.breedMachine.fetch(
+      breedingMachine
+    )
+
     expect(userMintABalance.value.uiAmount).to.equal(0)
     expect(userMintBBalance.value.uiAmount).to.equal(0)
     expect(breedMintABalance.value.uiAmount).to.equal(1)
     expect(breedMintBBalance.value.uiAmount).to.equal(1)
+    // A whitelist token is kept for the breeding until it's over.
+    expect(reserved.toNumber()).to.equal(1)
   })
 
   it("should be able to terminate a breeding", async () => {
-    const { tx, breedData, userWhitelistAta, userAtaParentB, userAtaParentA } =
-      await terminate(mintParentA, mintParentB, [userWallet])
+    const {
+      tx,
+      breedData,
+      userWhitelistAta,
+      userAtas: [userAtaParentA, userAtaParentB],
+    } = await terminate([mintParentA, mintParentB], [userWallet])
 
     console.log("Your transaction signature", tx)
     const userMintABalance =
@@ -193,20 +292,39 @@ describe("breed-program", () => {
     expect(oldBreedAccount).to.be.null
     expect(breedMachineAccount.born.toNumber()).to.greaterThanOrEqual(1)
     expect(breedMachineAccount.bred.toNumber()).to.greaterThanOrEqual(2)
+    expect(breedMachineAccount.reserved.toNumber()).to.equal(0)
     expect(userMintABalance.value.uiAmount).to.equal(1)
     expect(userMintBBalance.value.uiAmount).to.equal(1)
     expect(userWhitelistTokenBalance.value.uiAmount).to.greaterThanOrEqual(1)
   })
 
-  it("should be able to cancel a breeding", async () => {
-    await init(mintParentA, mintParentB, [userWallet])
+  it("should record the lineage of the last breeding", async () => {
+    const { born } = await program.account.breedMachine.fetch(breedingMachine)
 
-    const { tx, breedData, userAtaParentB, userAtaParentA } = await cancel(
-      mintParentA,
-      mintParentB,
-      [userWallet]
+    const lineage = await program.account.lineage.fetch(
+      findBreedLineageAddress(breedingMachine, born.subn(1), program.programId)
     )
 
+    const parents = lineage.parents.map((mint) => mint.toBase58())
+
+    expect(lineage.machine.toBase58()).to.equal(breedingMachine.toBase58())
+    expect(lineage.childMint).to.be.null
+    expect(parents).to.have.members([
+      mintParentA.toBase58(),
+      mintParentB.toBase58(),
+    ])
+    expect(lineage.generation).to.equal(1)
+  })
+
+  it("should be able to cancel a breeding", async () => {
+    await init([mintParentA, mintParentB], [userWallet])
+
+    const {
+      tx,
+      breedData,
+      userAtas: [userAtaParentA, userAtaParentB],
+    } = await cancel([mintParentA, mintParentB], [userWallet])
+
     console.log("Your transaction signature", tx)
 
     const userMintABalance =
@@ -229,4 +347,725 @@ describe("breed-program", () => {
     expect(userMintABalance.value.uiAmount).to.equal(1)
     expect(userMintBBalance.value.uiAmount).to.equal(1)
   })
+
+  it("should keep the parents frozen in the user wallet in freeze mode", async () => {
+    const updateLockMode = (lockMode: object) =>
+      program.methods
+        .updateMachineConfig({
+          initializationFeePrice: null,
+          breedingTime: null,
+          burnParents: null,
+          breedingCooldown: null,
+          maxBreedsPerParent: null,
+          parentsCollection: null,
+          parentValidation: null,
+          parentsCount: null,
+          feeMode: null,
+          feeTreasury: null,
+          solFeeLamports: null,
+          solFeeTreasury: null,
+          cancelPolicy: null,
+          lockMode,
+          successBps: null,
+        })
+        .accounts({
+          breedingMachine,
+          authority: breedingMachineAuthority.publicKey,
+        })
+        .signers([breedingMachineAuthority])
+        .rpc()
+
+    const getTokenAccount = async (address: anchor.web3.PublicKey) => {
+      const { value } =
+        await program.provider.connection.getParsedAccountInfo(address)
+
+      return (value?.data as anchor.web3.ParsedAccountData)?.parsed.info
+    }
+
+    await updateLockMode({ freeze: {} })
+
+    const {
+      userAtas: [userAtaParentA, userAtaParentB],
+      vaultAtas: [vaultAtaParentA],
+    } = await init([mintParentA, mintParentB], [userWallet])
+
+    for (const userAta of [userAtaParentA, userAtaParentB]) {
+      const account = await getTokenAccount(userAta)
+
+      expect(account.tokenAmount.uiAmount).to.equal(1)
+      expect(account.state).to.equal("frozen")
+    }
+    expect(await getTokenAccount(vaultAtaParentA)).to.be.undefined
+
+    // The breeding keeps its lock mode when the config changes.
+    await updateLockMode({ vault: {} })
+    await cancel([mintParentA, mintParentB], [userWallet])
+
+    for (const userAta of [userAtaParentA, userAtaParentB]) {
+      const account = await getTokenAccount(userAta)
+
+      expect(account.tokenAmount.uiAmount).to.equal(1)
+      expect(account.state).to.equal("initialized")
+      expect(account.delegate).to.be.undefined
+    }
+  })
+
+  it("should return the parents without a child when the breeding fails", async () => {
+    const updateSuccessBps = (successBps: number) =>
+      program.methods
+        .updateMachineConfig({
+          initializationFeePrice: null,
+          breedingTime: null,
+          burnParents: null,
+          breedingCooldown: null,
+          maxBreedsPerParent: null,
+          parentsCollection: null,
+          parentValidation: null,
+          parentsCount: null,
+          feeMode: null,
+          feeTreasury: null,
+          solFeeLamports: null,
+          solFeeTreasury: null,
+          cancelPolicy: null,
+          lockMode: null,
+          successBps,
+        })
+        .accounts({
+          breedingMachine,
+          authority: breedingMachineAuthority.publicKey,
+        })
+        .signers([breedingMachineAuthority])
+        .rpc()
+
+    const userWhitelistAta = await anchor.utils.token.associatedAddress({
+      mint: whitelistToken,
+      owner: userWallet.publicKey,
+    })
+
+    const getWhitelistBalance = async () =>
+      (
+        await program.provider.connection.getTokenAccountBalance(
+          userWhitelistAta
+        )
+      ).value.uiAmount
+
+    await updateSuccessBps(0)
+    await init([mintParentA, mintParentB], [userWallet])
+
+    // The outcome needs the hash of the slot after the breeding started.
+    await revealWhenReady([mintParentA, mintParentB])
+
+    const { born } = await program.account.breedMachine.fetch(breedingMachine)
+    const whitelistBalance = await getWhitelistBalance()
+
+    const {
+      userAtas: [userAtaParentA, userAtaParentB],
+    } = await terminate([mintParentA, mintParentB], [userWallet])
+
+    await updateSuccessBps(10000)
+
+    const machineAccount = await program.account.breedMachine.fetch(
+      breedingMachine
+    )
+
+    const userMintABalance =
+      await program.provider.connection.getTokenAccountBalance(userAtaParentA)
+
+    const userMintBBalance =
+      await program.provider.connection.getTokenAccountBalance(userAtaParentB)
+
+    expect(machineAccount.born.toNumber()).to.equal(born.toNumber())
+    expect(await getWhitelistBalance()).to.equal(whitelistBalance)
+    expect(userMintABalance.value.uiAmount).to.equal(1)
+    expect(userMintBBalance.value.uiAmount).to.equal(1)
+  })
+
+  it("should finalize with the config taken when the breeding started", async () => {
+    const { breedData } = await init([mintParentA, mintParentB], [userWallet])
+
+    await program.methods
+      .updateMachineConfig({
+        initializationFeePrice: null,
+        breedingTime: null,
+        burnParents: true,
+        breedingCooldown: null,
+        maxBreedsPerParent: null,
+        parentsCollection: null,
+        parentValidation: null,
+        parentsCount: null,
+        feeMode: null,
+        feeTreasury: null,
+        solFeeLamports: null,
+        solFeeTreasury: null,
+        cancelPolicy: null,
+        lockMode: null,
+        successBps: null,
+      })
+      .accounts({
+        breedingMachine,
+        authority: breedingMachineAuthority.publicKey,
+      })
+      .signers([breedingMachineAuthority])
+      .rpc()
+
+    const breedAccount = await program.account.breedData.fetch(breedData)
+
+    expect(breedAccount.burnParents).to.be.false
+    expect(breedAccount.feePaid.toNumber()).to.equal(1234)
+
+    const {
+      userAtas: [userAtaParentA, userAtaParentB],
+    } = await terminate([mintParentA, mintParentB], [userWallet])
+
+    const userMintABalance =
+      await program.provider.connection.getTokenAccountBalance(userAtaParentA)
+
+    const userMintBBalance =
+      await program.provider.connection.getTokenAccountBalance(userAtaParentB)
+
+    expect(userMintABalance.value.uiAmount).to.equal(1)
+    expect(userMintBBalance.value.uiAmount).to.equal(1)
+
+    await program.methods
+      .updateMachineConfig({
+        initializationFeePrice: null,
+        breedingTime: null,
+        burnParents: false,
+        breedingCooldown: null,
+        maxBreedsPerParent: null,
+        parentsCollection: null,
+        parentValidation: null,
+        parentsCount: null,
+        feeMode: null,
+        feeTreasury: null,
+        solFeeLamports: null,
+        solFeeTreasury: null,
+        cancelPolicy: null,
+        lockMode: null,
+        successBps: null,
+      })
+      .accounts({
+        breedingMachine,
+        authority: breedingMachineAuthority.publicKey,
+      })
+      .signers([breedingMachineAuthority])
+      .rpc()
+  })
+
+  it("should be able to set the extra parent collections", async () => {
+    const extraParentCollections = [
+      {
+        creator: anchor.web3.Keypair.generate().publicKey,
+        collection: anchor.web3.PublicKey.default,
+      },
+    ]
+
+    // Config collection (0) only breeds with itself, the extra one (1) with both.
+    const compatibility = [0b01, 0b11, 0, 0, 0, 0, 0, 0]
+
+    await program.methods
+      .setParentCollections(extraParentCollections, compatibility)
+      .accounts({
+        breedingMachine,
+        authority: breedingMachineAuthority.publicKey,
+      })
+      .signers([breedingMachineAuthority])
+      .rpc()
+
+    const machineAccount = await program.account.breedMachine.fetch(
+      breedingMachine
+    )
+
+    expect(machineAccount.extraParentCollections).to.have.lengthOf(1)
+    expect(machineAccount.compatibility).to.deep.equal(compatibility)
+
+    await expectBreedingError(
+      program.methods
+        .setParentCollections(
+          Array(8).fill(extraParentCollections[0]),
+          compatibility
+        )
+        .accounts({
+          breedingMachine,
+          authority: breedingMachineAuthority.publicKey,
+        })
+        .signers([breedingMachineAuthority])
+        .rpc(),
+      "TooManyParentCollections"
+    )
+  })
+
+  it("should not set a child NFT name that doesn't fit the metadata", async () => {
+    await expectBreedingError(
+      program.methods
+        .setChildNft({
+          collection: anchor.web3.Keypair.generate().publicKey,
+          name: "A name way too long for a metadata account",
+          symbol: "CHILD",
+          uriPrefix: "https://example.com/children/",
+          sellerFeeBasisPoints: 500,
+        })
+        .accounts({
+          breedingMachine,
+          authority: breedingMachineAuthority.publicKey,
+        })
+        .signers([breedingMachineAuthority])
+        .rpc(),
+      "InvalidChildNft"
+    )
+
+    const machineAccount = await program.account.breedMachine.fetch(
+      breedingMachine
+    )
+
+    expect(machineAccount.childNft).to.be.null
+  })
+
+  it("should not split fees over 100%", async () => {
+    await expectBreedingError(
+      program.methods
+        .updateMachineConfig({
+          initializationFeePrice: null,
+          breedingTime: null,
+          burnParents: null,
+          breedingCooldown: null,
+          maxBreedsPerParent: null,
+          parentsCollection: null,
+          parentValidation: null,
+          parentsCount: null,
+          feeMode: { split: { burnBps: 20000 } },
+          feeTreasury: null,
+          solFeeLamports: null,
+          solFeeTreasury: null,
+          cancelPolicy: null,
+          lockMode: null,
+          successBps: null,
+        })
+        .accounts({
+          breedingMachine,
+          authority: breedingMachineAuthority.publicKey,
+        })
+        .signers([breedingMachineAuthority])
+        .rpc(),
+      "InvalidBasisPoints"
+    )
+  })
+
+  it("should escrow the SOL fee and keep the cancel penalty", async () => {
+    const solFeeLamports = new anchor.BN(anchor.web3.LAMPORTS_PER_SOL / 100)
+    const solFeeTreasury = anchor.web3.Keypair.generate().publicKey
+
+    const updateSolFee = (lamports: anchor.BN, cancelPolicy: object) =>
+      program.methods
+        .updateMachineConfig({
+          initializationFeePrice: null,
+          breedingTime: null,
+          burnParents: null,
+          breedingCooldown: null,
+          maxBreedsPerParent: null,
+          parentsCollection: null,
+          parentValidation: null,
+          parentsCount: null,
+          feeMode: null,
+          feeTreasury: null,
+          solFeeLamports: lamports,
+          solFeeTreasury,
+          cancelPolicy,
+          lockMode: null,
+          successBps: null,
+        })
+        .accounts({
+          breedingMachine,
+          authority: breedingMachineAuthority.publicKey,
+        })
+        .signers([breedingMachineAuthority])
+        .rpc()
+
+    await updateSolFee(solFeeLamports, { partialRefund: { refundBps: 5000 } })
+
+    const { breedData } = await init([mintParentA, mintParentB], [userWallet])
+
+    const breedDataAccount = await program.account.breedData.fetch(breedData)
+    expect(breedDataAccount.solFeePaid.toNumber()).to.equal(
+      solFeeLamports.toNumber()
+    )
+
+    // The fee stays in escrow until the breeding is settled.
+    expect(
+      await program.provider.connection.getBalance(solFeeTreasury)
+    ).to.equal(0)
+
+    await cancel([mintParentA, mintParentB], [userWallet])
+
+    expect(
+      await program.provider.connection.getBalance(solFeeTreasury)
+    ).to.equal(solFeeLamports.toNumber() / 2)
+
+    await updateSolFee(new anchor.BN(0), { fullRefund: {} })
+  })
+
+  it("should not set a cancel penalty over 100%", async () => {
+    await expectBreedingError(
+      program.methods
+        .updateMachineConfig({
+          initializationFeePrice: null,
+          breedingTime: null,
+          burnParents: null,
+          breedingCooldown: null,
+          maxBreedsPerParent: null,
+          parentsCollection: null,
+          parentValidation: null,
+          parentsCount: null,
+          feeMode: null,
+          feeTreasury: null,
+          solFeeLamports: null,
+          solFeeTreasury: null,
+          cancelPolicy: { timePenalty: { maxPenaltyBps: 20000 } },
+          lockMode: null,
+          successBps: null,
+        })
+        .accounts({
+          breedingMachine,
+          authority: breedingMachineAuthority.publicKey,
+        })
+        .signers([breedingMachineAuthority])
+        .rpc(),
+      "InvalidBasisPoints"
+    )
+  })
+
+  it("should not let a third party finalize someone else's breeding", async () => {
+    await init([mintParentA, mintParentB], [userWallet])
+
+    const airdrop = await program.provider.connection.requestAirdrop(
+      thirdParty.publicKey,
+      anchor.web3.LAMPORTS_PER_SOL
+    )
+    await program.provider.connection.confirmTransaction(airdrop)
+
+    await expectBreedingError(
+      thirdPartyBreeding.terminate([mintParentA, mintParentB], [thirdParty]),
+      "InvalidBreedOwner"
+    )
+  })
+
+  it("should not let a third party cancel someone else's breeding", async () => {
+    await expectBreedingError(
+      thirdPartyBreeding.cancel([mintParentA, mintParentB], [thirdParty]),
+      "InvalidBreedOwner"
+    )
+
+    const {
+      breedData,
+      userAtas: [userAtaParentA, userAtaParentB],
+    } = await cancel([mintParentA, mintParentB], [userWallet])
+
+    const oldBreedAccount = await program.account.breedData.fetchNullable(
+      breedData
+    )
+
+    const userMintABalance =
+      await program.provider.connection.getTokenAccountBalance(userAtaParentA)
+
+    const userMintBBalance =
+      await program.provider.connection.getTokenAccountBalance(userAtaParentB)
+
+    expect(oldBreedAccount).to.be.null
+    expect(userMintABalance.value.uiAmount).to.equal(1)
+    expect(userMintBBalance.value.uiAmount).to.equal(1)
+  })
+
+  it("should not initialize breedings while the machine is paused", async () => {
+    await program.methods
+      .pauseMachine()
+      .accounts({
+        breedingMachine,
+        authority: breedingMachineAuthority.publicKey,
+      })
+      .signers([breedingMachineAuthority])
+      .rpc()
+
+    await expectBreedingError(
+      init([mintParentA, mintParentB], [userWallet]),
+      "MachinePaused"
+    )
+
+    await program.methods
+      .resumeMachine()
+      .accounts({
+        breedingMachine,
+        authority: breedingMachineAuthority.publicKey,
+      })
+      .signers([breedingMachineAuthority])
+      .rpc()
+
+    const machineAccount = await program.account.breedMachine.fetch(
+      breedingMachine
+    )
+
+    expect(machineAccount.paused).to.be.false
+  })
+
+  it("should be able to transfer the machine authority", async () => {
+    const newAuthority = anchor.web3.Keypair.generate()
+
+    await program.methods
+      .proposeAuthority(newAuthority.publicKey)
+      .accounts({
+        breedingMachine,
+        authority: breedingMachineAuthority.publicKey,
+      })
+      .signers([breedingMachineAuthority])
+      .rpc()
+
+    await expectBreedingError(
+      program.methods
+        .acceptAuthority()
+        .accounts({ breedingMachine, newAuthority: thirdParty.publicKey })
+        .signers([thirdParty])
+        .rpc(),
+      "InvalidPendingAuthority"
+    )
+
+    await program.methods
+      .acceptAuthority()
+      .accounts({ breedingMachine, newAuthority: newAuthority.publicKey })
+      .signers([newAuthority])
+      .rpc()
+
+    let machineAccount = await program.account.breedMachine.fetch(
+      breedingMachine
+    )
+
+    expect(machineAccount.authority.toBase58()).to.equal(
+      newAuthority.publicKey.toBase58()
+    )
+    expect(machineAccount.creator.toBase58()).to.equal(
+      breedingMachineAuthority.publicKey.toBase58()
+    )
+    expect(machineAccount.pendingAuthority).to.be.null
+
+    // Hand it back so the remaining tests keep working.
+    await program.methods
+      .proposeAuthority(breedingMachineAuthority.publicKey)
+      .accounts({ breedingMachine, authority: newAuthority.publicKey })
+      .signers([newAuthority])
+      .rpc()
+
+    await program.methods
+      .acceptAuthority()
+      .accounts({
+        breedingMachine,
+        newAuthority: breedingMachineAuthority.publicKey,
+      })
+      .signers([breedingMachineAuthority])
+      .rpc()
+
+    machineAccount = await program.account.breedMachine.fetch(breedingMachine)
+
+    expect(machineAccount.authority.toBase58()).to.equal(
+      breedingMachineAuthority.publicKey.toBase58()
+    )
+  })
+
+  it("should not breed parents in cooldown", async () => {
+    const parentRecord = await program.account.parentRecord.fetch(
+      findParentRecordAddress(mintParentA, program.programId)
+    )
+
+    expect(parentRecord.timesBred.toNumber()).to.greaterThanOrEqual(1)
+
+    await program.methods
+      .updateMachineConfig({
+        initializationFeePrice: null,
+        breedingTime: null,
+        burnParents: null,
+        breedingCooldown: new anchor.BN(60 * 60 * 24),
+        maxBreedsPerParent: null,
+        parentsCollection: null,
+        parentValidation: null,
+        parentsCount: null,
+        feeMode: null,
+        feeTreasury: null,
+        solFeeLamports: null,
+        solFeeTreasury: null,
+        cancelPolicy: null,
+        lockMode: null,
+        successBps: null,
+      })
+      .accounts({
+        breedingMachine,
+        authority: breedingMachineAuthority.publicKey,
+      })
+      .signers([breedingMachineAuthority])
+      .rpc()
+
+    await expectBreedingError(
+      init([mintParentA, mintParentB], [userWallet]),
+      "ParentInCooldown"
+    )
+
+    await program.methods
+      .updateMachineConfig({
+        initializationFeePrice: null,
+        breedingTime: null,
+        burnParents: null,
+        breedingCooldown: new anchor.BN(0),
+        maxBreedsPerParent: null,
+        parentsCollection: null,
+        parentValidation: null,
+        parentsCount: null,
+        feeMode: null,
+        feeTreasury: null,
+        solFeeLamports: null,
+        solFeeTreasury: null,
+        cancelPolicy: null,
+        lockMode: null,
+        successBps: null,
+      })
+      .accounts({
+        breedingMachine,
+        authority: breedingMachineAuthority.publicKey,
+      })
+      .signers([breedingMachineAuthority])
+      .rpc()
+  })
+
+  it("should not breed an NFT with itself", async () => {
+    await expectBreedingError(
+      init([mintParentA, mintParentA], [userWallet]),
+      "SelfBreeding"
+    )
+  })
+
+  it("should only breed the configured number of parents", async () => {
+    await expectBreedingError(
+      init([mintParentA], [userWallet]),
+      "InvalidParentCount"
+    )
+  })
+
+  it("should replenish the rewards up to the cap", async () => {
+    const whitelistVault = await anchor.utils.token.associatedAddress({
+      mint: whitelistToken,
+      owner: breedingMachine,
+    })
+
+    const replenish = (amount: number) =>
+      program.methods
+        .replenishRewards(new anchor.BN(amount))
+        .accounts({
+          breedingMachine,
+          whitelistToken,
+          whitelistVault,
+          authority: breedingMachineAuthority.publicKey,
+        })
+        .signers([breedingMachineAuthority])
+        .rpc()
+
+    const before = await program.provider.connection.getTokenAccountBalance(
+      whitelistVault
+    )
+
+    await replenish(10)
+
+    const after = await program.provider.connection.getTokenAccountBalance(
+      whitelistVault
+    )
+    const { config } = await program.account.breedMachine.fetch(
+      breedingMachine
+    )
+
+    expect(after.value.uiAmount).to.equal(before.value.uiAmount + 10)
+    expect(config.rewardSupply.toNumber()).to.equal(3343)
+
+    await expectBreedingError(replenish(1), "RewardCapExceeded")
+  })
+
+  it("should add a reward tier with its own whitelist token", async () => {
+    const tierToken = findRewardTierTokenAddress(
+      breedingMachine,
+      1,
+      program.programId
+    )
+    const tierVault = await anchor.utils.token.associatedAddress({
+      mint: tierToken,
+      owner: breedingMachine,
+    })
+
+    await program.methods
+      .addRewardTier(1, 1, new anchor.BN(5))
+      .accounts({
+        breedingMachine,
+        tierToken,
+        tierVault,
+        authority: breedingMachineAuthority.publicKey,
+      })
+      .signers([breedingMachineAuthority])
+      .rpc()
+
+    const { extraRewardTiers } = await program.account.breedMachine.fetch(
+      breedingMachine
+    )
+    const tierVaultBalance =
+      await program.provider.connection.getTokenAccountBalance(tierVault)
+
+    expect(extraRewardTiers).to.have.length(1)
+    expect(extraRewardTiers[0].weight).to.equal(1)
+    expect(tierVaultBalance.value.uiAmount).to.equal(5)
+  })
+
+  it("should be able to close a machine", async () => {
+    const whitelistVault = await anchor.utils.token.associatedAddress({
+      mint: whitelistToken,
+      owner: breedingMachine,
+    })
+
+    const { born, extraRewardTiers } =
+      await program.account.breedMachine.fetch(breedingMachine)
+
+    // The vaults of the other reward tiers are closed too.
+    const rewardTierAccounts = await getRewardTierAccounts(
+      breedingMachine,
+      extraRewardTiers.length,
+      program.programId
+    )
+
+    const tx = await program.methods
+      .closeMachine()
+      .accounts({
+        breedingMachine,
+        whitelistToken,
+        whitelistVault,
+        authority: breedingMachineAuthority.publicKey,
+      })
+      .remainingAccounts(rewardTierAccounts)
+      .signers([breedingMachineAuthority])
+      .rpc()
+
+    console.log("Your transaction signature", tx)
+
+    const machineAccount = await program.account.breedMachine.fetchNullable(
+      breedingMachine
+    )
+
+    const whitelistVaultAccount =
+      await program.provider.connection.getAccountInfo(whitelistVault)
+
+    const wlTokenSupply = await program.provider.connection.getTokenSupply(
+      whitelistToken
+    )
+
+    expect(machineAccount).to.be.null
+    expect(whitelistVaultAccount).to.be.null
+    // Mint then vault of each tier.
+    const tierVaults = rewardTierAccounts.filter((_, i) => i % 2 === 1)
+    for (const { pubkey } of tierVaults) {
+      const tierVaultAccount =
+        await program.provider.connection.getAccountInfo(pubkey)
+      expect(tierVaultAccount).to.be.null
+    }
+    // Only the claimed whitelist tokens are left in circulation.
+    expect(wlTokenSupply.value.uiAmount).to.equal(born.toNumber())
+  })
 })
@@ -76,8 +76,22 @@ describe("breed-program", () => {
       rewardSupply: new anchor.BN(2222),
       initializationFeeToken: feeToken,
       initializationFeePrice: new anchor.BN(3630),
+      feeMode: { burn: {} },
+      feeTreasury: anchor.web3.PublicKey.default,
+      solFeeLamports: new anchor.BN(0),
+      solFeeTreasury: anchor.web3.PublicKey.default,
+      cancelPolicy: { fullRefund: {} },
+      lockMode: { vault: {} },
+      successBps: 10000,
+      mintCapped: false,
+      rewardCap: new anchor.BN(0),
       rewardCandyMachine: rewardsCandyMachineAddress,
       parentsCandyMachine: parentsCandyMachineAddress,
+      parentsCollection: anchor.web3.PublicKey.default,
+      parentValidation: { verifiedCreator: {} },
+      parentsCount: 2,
+      breedingCooldown: new anchor.BN(0),
+      maxBreedsPerParent: new anchor.BN(0),
     }
 
     const whitelistVault = await anchor.utils.token.associatedAddress({
@@ -123,6 +137,18 @@ describe("breed-program", () => {
         initializationFeePrice: newPrice,
         breedingTime: new anchor.BN(0),
         burnParents: true,
+        breedingCooldown: null,
+        maxBreedsPerParent: null,
+        parentsCollection: null,
+        parentValidation: null,
+        parentsCount: null,
+        feeMode: null,
+        feeTreasury: null,
+        solFeeLamports: null,
+        solFeeTreasury: null,
+        cancelPolicy: null,
+        lockMode: null,
+        successBps: null,
       })
       .accounts({
         breedingMachine,
@@ -145,11 +171,9 @@ describe("breed-program", () => {
   it.skip("should be able to initialize a breeding", async () => {
     const {
       tx,
-      userAtaParentA,
-      userAtaParentB,
-      vaultAtaParentA,
-      vaultAtaParentB,
-    } = await init(mintParentA, mintParentB, [userWallet])
+      userAtas: [userAtaParentA, userAtaParentB],
+      vaultAtas: [vaultAtaParentA, vaultAtaParentB],
+    } = await init([mintParentA, mintParentB], [userWallet])
 
     console.log("Your transaction signature", tx)
 
@@ -172,8 +196,12 @@ describe("breed-program", () => {
   })
 
   it.skip("should be able to terminate a breeding", async () => {
-    const { tx, breedData, userWhitelistAta, userAtaParentB, userAtaParentA } =
-      await terminate(mintParentA, mintParentB, [userWallet])
+    const {
+      tx,
+      breedData,
+      userWhitelistAta,
+      userAtas: [userAtaParentA, userAtaParentB],
+    } = await terminate([mintParentA, mintParentB], [userWallet])
 
     console.log("Your transaction signature", tx)
     const userMintABalance =
@@ -202,13 +230,13 @@ describe("breed-program", () => {
   })
 
   it.skip("should be able to cancel a breeding", async () => {
-    await init(mintParentA, mintParentB, [userWallet])
+    await init([mintParentA, mintParentB], [userWallet])
 
-    const { tx, breedData, userAtaParentB, userAtaParentA } = await cancel(
-      mintParentA,
-      mintParentB,
-      [userWallet]
-    )
+    const {
+      tx,
+      breedData,
+      userAtas: [userAtaParentA, userAtaParentB],
+    } = await cancel([mintParentA, mintParentB], [userWallet])
 
     console.log("Your transaction signature", tx)
 